@@ -0,0 +1,414 @@
+//format binaire du bytecode
+//
+//  magic "MZAM", version (u16 petit-boutiste), drapeaux (u8)
+//  table des opcodes : nombre, puis le nom de chaque opcode
+//  instructions : nombre, puis pour chacune son opcode (u8) et ses arguments
+//  section des labels si le drapeau SYMBOLS est présent : nombre, puis
+//  (indice d'instruction, nom) pour chaque label
+//
+//les entiers sont codés en LEB128, les flottants par leurs 8 octets IEEE 754
+//petit-boutistes, les cibles des sauts sont relatives à
+//l'instruction qui saute. Un opcode est désigné par son nom dans la table,
+//un fichier reste donc lisible si des instructions sont ajoutées.
+use crate::linker::{self, LinkError};
+use crate::machine::Inst;
+use crate::Program;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Read, Write};
+
+pub const MAGIC: &[u8; 4] = b"MZAM";
+pub const VERSION: u16 = 2;
+//la version 1 n'a pas d'étiquette après la taille de MAKEBLOCK
+const VERSION_UNTAGGED: u16 = 1;
+
+const SYMBOLS: u8 = 1;
+
+const OPCODES: [&str; 31] = [
+    "CONST",
+    "PRIM",
+    "BRANCH",
+    "BRANCHIFNOT",
+    "PUSH",
+    "POP",
+    "ACC",
+    "ENVACC",
+    "CLOSURE",
+    "APPLY",
+    "RETURN",
+    "STOP",
+    "CLOSUREREC",
+    "OFFSETCLOSURE",
+    "GRAB",
+    "RESTART",
+    "APPTERM",
+    "MAKEBLOCK",
+    "GETFIELD",
+    "VECTLENGTH",
+    "GETVECTITEM",
+    "SETFIELD",
+    "SETVECTITEM",
+    "ASSIGN",
+    "PUSHTRAP",
+    "POPTRAP",
+    "RAISE",
+    "CONSTSTRING",
+    "CONSTFLOAT",
+    "GETTAG",
+    "SWITCH",
+];
+
+#[derive(Debug)]
+pub enum BytecodeError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    //opcode de la table que cette version ne connaît pas
+    UnknownOpcode(String),
+    //opcode absent de la table du fichier
+    BadOpcode(u8),
+    BadTarget(i64),
+    //entier LEB128 trop long ou hors des 64 bits
+    BadInteger,
+    BadString,
+    Link(Vec<LinkError>),
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BytecodeError::Io(e) => write!(f, "{}", e),
+            BytecodeError::BadMagic => write!(f, "ce n'est pas un fichier de bytecode"),
+            BytecodeError::UnsupportedVersion(v) => {
+                write!(f, "version {} du bytecode non supportée", v)
+            }
+            BytecodeError::UnknownOpcode(name) => write!(f, "opcode inconnu {}", name),
+            BytecodeError::BadOpcode(op) => write!(f, "opcode {} absent de la table", op),
+            BytecodeError::BadTarget(t) => write!(f, "instruction {} hors du programme", t),
+            BytecodeError::BadInteger => write!(f, "entier mal codé"),
+            BytecodeError::BadString => write!(f, "chaîne mal formée"),
+            BytecodeError::Link(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", errors.join("\n"))
+            }
+        }
+    }
+}
+
+impl std::error::Error for BytecodeError {}
+
+impl From<io::Error> for BytecodeError {
+    fn from(e: io::Error) -> BytecodeError {
+        BytecodeError::Io(e)
+    }
+}
+
+//vrai si les premiers octets sont ceux d'un fichier de bytecode
+pub fn is_bytecode(start: &[u8]) -> bool {
+    start.starts_with(MAGIC)
+}
+
+pub fn write_bytecode<W: Write>(
+    prog: &[(Option<String>, Inst)],
+    out: &mut W,
+    symbols: bool,
+) -> Result<(), BytecodeError> {
+    let code = linker::link(prog).map_err(BytecodeError::Link)?;
+
+    out.write_all(MAGIC)?;
+    out.write_all(&VERSION.to_le_bytes())?;
+    out.write_all(&[if symbols { SYMBOLS } else { 0 }])?;
+
+    write_unsigned(out, OPCODES.len() as u64)?;
+    for name in OPCODES.iter() {
+        write_string(out, name)?;
+    }
+
+    write_unsigned(out, code.len() as u64)?;
+    for (pc, inst) in code.iter().enumerate() {
+        let opcode = OPCODES.iter().position(|o| *o == inst.mnemonic()).unwrap();
+        out.write_all(&[opcode as u8])?;
+        let offset = |target: &usize| *target as i64 - pc as i64;
+        match inst {
+            Inst::Const(n)
+            | Inst::Apply(n)
+            | Inst::Return(n)
+            | Inst::Grab(n)
+            | Inst::Getfield(n)
+            | Inst::Setfield(n)
+            | Inst::Assign(n) => write_signed(out, *n)?,
+            Inst::Acc(n) | Inst::Envacc(n) => write_unsigned(out, *n)?,
+            Inst::Prim(op) | Inst::ConstString(op) => write_string(out, op)?,
+            Inst::ConstFloat(x) => out.write_all(&x.to_bits().to_le_bytes())?,
+            Inst::Branch(l) | Inst::BranchIfNot(l) | Inst::PushTrap(l) => {
+                write_signed(out, offset(l))?
+            }
+            Inst::Closure(l, n) | Inst::ClosureRec(l, n) => {
+                write_signed(out, offset(l))?;
+                write_signed(out, *n)?;
+            }
+            Inst::Switch(consts, tags) => {
+                for table in [consts, tags].iter() {
+                    write_unsigned(out, table.len() as u64)?;
+                    for target in table.iter() {
+                        write_signed(out, offset(target))?;
+                    }
+                }
+            }
+            Inst::AppTerm(n, m) | Inst::Makeblock(n, m) => {
+                write_signed(out, *n)?;
+                write_signed(out, *m)?;
+            }
+            _ => {}
+        }
+    }
+
+    if symbols {
+        let labels: Vec<(usize, &String)> = prog
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (l, _))| l.as_ref().map(|l| (i, l)))
+            .collect();
+        write_unsigned(out, labels.len() as u64)?;
+        for (i, l) in labels {
+            write_unsigned(out, i as u64)?;
+            write_string(out, l)?;
+        }
+    }
+    Ok(())
+}
+
+//sans section des labels, chaque cible reçoit un label Ln, n étant son indice
+pub fn read_bytecode<R: Read>(input: &mut R) -> Result<Program, BytecodeError> {
+    let mut magic = [0u8; 4];
+    input.read_exact(&mut magic)?;
+    if !is_bytecode(&magic) {
+        return Err(BytecodeError::BadMagic);
+    }
+    let mut version = [0u8; 2];
+    input.read_exact(&mut version)?;
+    let version = u16::from_le_bytes(version);
+    if version != VERSION && version != VERSION_UNTAGGED {
+        return Err(BytecodeError::UnsupportedVersion(version));
+    }
+    let flags = read_byte(input)?;
+
+    let mut table = Vec::new();
+    for _ in 0..read_unsigned(input)? {
+        let name = read_string(input)?;
+        match OPCODES.iter().find(|o| **o == name) {
+            Some(o) => table.push(*o),
+            None => return Err(BytecodeError::UnknownOpcode(name)),
+        }
+    }
+
+    let count = read_unsigned(input)? as usize;
+    //le nombre annoncé n'est pas fiable avant d'avoir lu les instructions
+    let mut code: Vec<Inst<i64>> = Vec::with_capacity(count.min(1 << 16));
+    for pc in 0..count as i64 {
+        let opcode = read_byte(input)?;
+        let name = match table.get(opcode as usize) {
+            Some(name) => *name,
+            None => return Err(BytecodeError::BadOpcode(opcode)),
+        };
+        let inst = match name {
+            "CONST" => Inst::Const(read_signed(input)?),
+            "PRIM" => Inst::Prim(read_string(input)?),
+            "BRANCH" => Inst::Branch(read_target(input, pc, count)?),
+            "BRANCHIFNOT" => Inst::BranchIfNot(read_target(input, pc, count)?),
+            "PUSH" => Inst::Push,
+            "POP" => Inst::Pop,
+            "ACC" => Inst::Acc(read_unsigned(input)?),
+            "ENVACC" => Inst::Envacc(read_unsigned(input)?),
+            "CLOSURE" => {
+                let target = read_target(input, pc, count)?;
+                Inst::Closure(target, read_signed(input)?)
+            }
+            "APPLY" => Inst::Apply(read_signed(input)?),
+            "RETURN" => Inst::Return(read_signed(input)?),
+            "STOP" => Inst::Stop,
+            "CLOSUREREC" => {
+                let target = read_target(input, pc, count)?;
+                Inst::ClosureRec(target, read_signed(input)?)
+            }
+            "OFFSETCLOSURE" => Inst::OffSetClosure,
+            "GRAB" => Inst::Grab(read_signed(input)?),
+            "RESTART" => Inst::Restart,
+            "APPTERM" => {
+                let n = read_signed(input)?;
+                Inst::AppTerm(n, read_signed(input)?)
+            }
+            "MAKEBLOCK" => {
+                let n = read_signed(input)?;
+                let tag = if version == VERSION_UNTAGGED {
+                    0
+                } else {
+                    read_signed(input)?
+                };
+                Inst::Makeblock(n, tag)
+            }
+            "GETFIELD" => Inst::Getfield(read_signed(input)?),
+            "VECTLENGTH" => Inst::Veclength,
+            "GETVECTITEM" => Inst::Getvectitem,
+            "GETTAG" => Inst::GetTag,
+            "SWITCH" => {
+                let mut tables = Vec::new();
+                for _ in 0..2 {
+                    //le nombre annoncé n'est pas fiable avant d'avoir lu les cibles
+                    let len = read_unsigned(input)?;
+                    let mut table = Vec::with_capacity(len.min(1 << 8) as usize);
+                    for _ in 0..len {
+                        table.push(read_target(input, pc, count)?);
+                    }
+                    tables.push(table);
+                }
+                let tags = tables.pop().unwrap();
+                Inst::Switch(tables.pop().unwrap().into(), tags.into())
+            }
+            "SETFIELD" => Inst::Setfield(read_signed(input)?),
+            "SETVECTITEM" => Inst::SetVectitem,
+            "ASSIGN" => Inst::Assign(read_signed(input)?),
+            "PUSHTRAP" => Inst::PushTrap(read_target(input, pc, count)?),
+            "POPTRAP" => Inst::PopTrap,
+            "RAISE" => Inst::Raise,
+            "CONSTSTRING" => Inst::ConstString(read_string(input)?),
+            "CONSTFLOAT" => {
+                let mut bits = [0u8; 8];
+                input.read_exact(&mut bits)?;
+                Inst::ConstFloat(f64::from_bits(u64::from_le_bytes(bits)))
+            }
+            _ => return Err(BytecodeError::UnknownOpcode(name.to_string())),
+        };
+        code.push(inst);
+    }
+
+    let mut names: HashMap<usize, String> = HashMap::new();
+    if flags & SYMBOLS != 0 {
+        for _ in 0..read_unsigned(input)? {
+            let i = read_unsigned(input)? as usize;
+            let name = read_string(input)?;
+            if i >= count {
+                return Err(BytecodeError::BadTarget(i as i64));
+            }
+            names.insert(i, name);
+        }
+    }
+    //les labels synthétisés commencent par un point pour ne pas prendre le nom
+    //d'un label de la section, même si elle contient des noms en .L
+    let taken: HashSet<String> = names.values().cloned().collect();
+    for inst in &code {
+        for target in inst.targets() {
+            let target = *target as usize;
+            names.entry(target).or_insert_with(|| {
+                let mut name = format!(".L{}", target);
+                while taken.contains(&name) {
+                    name.insert(0, '.');
+                }
+                name
+            });
+        }
+    }
+
+    let prog: Program = code
+        .iter()
+        .enumerate()
+        .map(|(i, inst)| {
+            let inst = inst.map_labels(|target| names[&(*target as usize)].clone());
+            (names.get(&i).cloned(), inst)
+        })
+        .collect();
+    //une section des labels mal formée peut nommer deux instructions pareil
+    linker::check(&prog).map_err(BytecodeError::Link)?;
+    Ok(prog)
+}
+
+fn write_unsigned<W: Write>(out: &mut W, mut n: u64) -> io::Result<()> {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            return out.write_all(&[byte]);
+        }
+        out.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn write_signed<W: Write>(out: &mut W, mut n: i64) -> io::Result<()> {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        let done = (n == 0 && byte & 0x40 == 0) || (n == -1 && byte & 0x40 != 0);
+        if done {
+            return out.write_all(&[byte]);
+        }
+        out.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn write_string<W: Write>(out: &mut W, s: &str) -> io::Result<()> {
+    write_unsigned(out, s.len() as u64)?;
+    out.write_all(s.as_bytes())
+}
+
+fn read_byte<R: Read>(input: &mut R) -> io::Result<u8> {
+    let mut byte = [0u8; 1];
+    input.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_unsigned<R: Read>(input: &mut R) -> Result<u64, BytecodeError> {
+    let mut n = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = read_byte(input)?;
+        //le dixième octet ne porte plus que le bit 63
+        if shift >= 64 || (shift == 63 && byte & 0x7f > 1) {
+            return Err(BytecodeError::BadInteger);
+        }
+        n |= ((byte & 0x7f) as u64) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+}
+
+fn read_signed<R: Read>(input: &mut R) -> Result<i64, BytecodeError> {
+    let mut n = 0i64;
+    let mut shift = 0;
+    loop {
+        let byte = read_byte(input)?;
+        //le dixième octet porte le bit 63, ses autres bits en sont l'extension
+        if shift >= 64 || (shift == 63 && byte & 0x7f != 0 && byte & 0x7f != 0x7f) {
+            return Err(BytecodeError::BadInteger);
+        }
+        n |= ((byte & 0x7f) as i64) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            if shift < 64 && byte & 0x40 != 0 {
+                n |= -1i64 << shift;
+            }
+            return Ok(n);
+        }
+    }
+}
+
+//cible absolue d'un saut codé relativement à pc
+fn read_target<R: Read>(input: &mut R, pc: i64, count: usize) -> Result<i64, BytecodeError> {
+    let offset = read_signed(input)?;
+    match pc.checked_add(offset) {
+        Some(target) if target >= 0 && (target as u64) < count as u64 => Ok(target),
+        _ => Err(BytecodeError::BadTarget(pc.saturating_add(offset))),
+    }
+}
+
+fn read_string<R: Read>(input: &mut R) -> Result<String, BytecodeError> {
+    let len = read_unsigned(input)? as usize;
+    let mut bytes = Vec::new();
+    input.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(BytecodeError::Io(io::ErrorKind::UnexpectedEof.into()));
+    }
+    String::from_utf8(bytes).map_err(|_| BytecodeError::BadString)
+}
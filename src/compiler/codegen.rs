@@ -0,0 +1,826 @@
+use std::collections::HashMap;
+
+use crate::compiler::lexer::Pos;
+use crate::compiler::syntax::{BinOp, Expr, Item, Pattern};
+use crate::compiler::CompileError;
+use crate::machine::Inst;
+use crate::primitives::PREDEFINED_EXCEPTIONS;
+
+//primitives utilisables comme des fonctions, avec leur arité
+const PRIMITIVES: [(&str, usize); 23] = [
+    ("print_char", 1),
+    ("not", 1),
+    ("abs", 1),
+    ("ref", 1),
+    ("raise", 1),
+    ("fst", 1),
+    ("snd", 1),
+    ("ignore", 1),
+    ("Array.length", 1),
+    ("String.length", 1),
+    ("print_string", 1),
+    ("string_of_int", 1),
+    ("int_of_string", 1),
+    ("float_of_int", 1),
+    ("int_of_float", 1),
+    ("sqrt", 1),
+    ("exp", 1),
+    ("log", 1),
+    ("sin", 1),
+    ("cos", 1),
+    ("string_of_float", 1),
+    ("print_float", 1),
+    ("Hashtbl.hash", 1),
+];
+
+enum Code {
+    Label(String),
+    Inst(Inst),
+}
+
+//accès à une variable depuis le corps d'une fonction
+enum Access {
+    //position dans la pile depuis le bas du bloc d'activation
+    Local(usize),
+    Env(usize),
+    //la fonction récursive elle-même
+    SelfRec,
+}
+
+struct Function {
+    code: Vec<Code>,
+    locals: Vec<(String, usize)>,
+    //variables libres capturées dans l'env, dans l'ordre de l'env
+    free: Vec<String>,
+    rec_name: Option<String>,
+    //taille courante de la pile du bloc d'activation
+    sz: usize,
+}
+
+impl Function {
+    fn new(rec_name: Option<String>) -> Function {
+        Function {
+            code: Vec::new(),
+            locals: Vec::new(),
+            free: Vec::new(),
+            rec_name,
+            sz: 0,
+        }
+    }
+
+    //dans une fermeture récursive env[0] contient le pc de la fonction
+    fn env_offset(&self) -> usize {
+        if self.rec_name.is_some() {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+pub struct Generator {
+    functions: Vec<Function>,
+    //code des fonctions déjà compilées, placé après le STOP
+    finished: Vec<Code>,
+    //exceptions déclarées, numérotées dans l'ordre à partir de 0
+    exceptions: HashMap<String, i64>,
+    //fermetures qui enveloppent les primitives passées en argument
+    wrappers: HashMap<String, String>,
+    next_label: usize,
+}
+
+impl Generator {
+    pub fn new() -> Generator {
+        Generator {
+            functions: vec![Function::new(None)],
+            finished: Vec::new(),
+            exceptions: HashMap::new(),
+            wrappers: HashMap::new(),
+            next_label: 1,
+        }
+    }
+
+    fn current(&mut self) -> &mut Function {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn emit(&mut self, inst: Inst) {
+        let f = self.current();
+        match inst {
+            Inst::Push => f.sz += 1,
+            Inst::Pop => f.sz -= 1,
+            _ => {}
+        }
+        f.code.push(Code::Inst(inst));
+    }
+
+    fn new_label(&mut self) -> String {
+        let l = format!("L{}", self.next_label);
+        self.next_label += 1;
+        l
+    }
+
+    fn place(&mut self, label: &str) {
+        self.current().code.push(Code::Label(label.to_string()));
+    }
+
+    pub fn program(mut self, items: &[Item]) -> Result<Vec<(Option<String>, Inst)>, CompileError> {
+        let mut bound = 0;
+        for item in items {
+            match item {
+                Item::Exception(name) => {
+                    let id = self.exceptions.len() as i64;
+                    self.exceptions.insert(name.clone(), id);
+                }
+                Item::Let(rec, pat, value, at) => {
+                    bound += self.bind(*rec, pat, value, *at)?;
+                }
+                Item::Expr(e) => self.expr(e, false)?,
+            }
+        }
+        for _ in 0..bound {
+            self.emit(Inst::Pop);
+        }
+        self.emit(Inst::Stop);
+
+        let mut main = self.functions.pop().unwrap().code;
+        main.append(&mut self.finished);
+        Ok(link(main))
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<Access> {
+        let depth = self.functions.len() - 1;
+        self.lookup_at(name, depth)
+    }
+
+    fn lookup_at(&mut self, name: &str, depth: usize) -> Option<Access> {
+        let f = &self.functions[depth];
+        if let Some((_, slot)) = f.locals.iter().rev().find(|(n, _)| n == name) {
+            return Some(Access::Local(*slot));
+        }
+        if f.rec_name.as_deref() == Some(name) {
+            return Some(Access::SelfRec);
+        }
+        if let Some(i) = f.free.iter().position(|n| n == name) {
+            return Some(Access::Env(i + f.env_offset()));
+        }
+        if depth == 0 {
+            return None;
+        }
+        //capturée si elle est visible dans la fonction englobante
+        self.lookup_at(name, depth - 1)?;
+        let f = &mut self.functions[depth];
+        f.free.push(name.to_string());
+        Some(Access::Env(f.free.len() - 1 + f.env_offset()))
+    }
+
+    fn load(&mut self, name: &str, pos: Pos) -> Result<(), CompileError> {
+        match self.lookup(name) {
+            Some(Access::Local(slot)) => {
+                let sz = self.current().sz;
+                self.emit(Inst::Acc((sz - slot - 1) as u64));
+            }
+            Some(Access::Env(i)) => self.emit(Inst::Envacc(i as u64)),
+            Some(Access::SelfRec) => self.emit(Inst::OffSetClosure),
+            None => {
+                if PRIMITIVES.iter().any(|(p, _)| *p == name) {
+                    let label = self.wrapper(name);
+                    self.emit(Inst::Closure(label, 0));
+                } else {
+                    return Err(CompileError::new(
+                        pos,
+                        &format!("variable inconnue {}", name),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn push_local(&mut self, name: &str) {
+        self.emit(Inst::Push);
+        let f = self.current();
+        let slot = f.sz - 1;
+        f.locals.push((name.to_string(), slot));
+    }
+
+    //termine une expression en position terminale
+    fn ret(&mut self, tail: bool) {
+        if tail {
+            let sz = self.current().sz;
+            self.emit(Inst::Return(sz as i64));
+        }
+    }
+
+    fn pops(&mut self, n: usize, tail: bool) {
+        if tail {
+            //le RETURN a déjà vidé la pile
+            self.current().sz -= n;
+        } else {
+            for _ in 0..n {
+                self.emit(Inst::Pop);
+            }
+        }
+    }
+
+    fn forget_locals(&mut self, count: usize) {
+        let f = self.current();
+        let len = f.locals.len();
+        f.locals.truncate(len - count);
+    }
+
+    pub fn expr(&mut self, e: &Expr, tail: bool) -> Result<(), CompileError> {
+        match e {
+            Expr::Int(n) => {
+                self.emit(Inst::Const(*n));
+                self.ret(tail);
+            }
+            Expr::Float(x) => {
+                self.emit(Inst::ConstFloat(*x));
+                self.ret(tail);
+            }
+            Expr::Str(s) => {
+                self.emit(Inst::ConstString(s.clone()));
+                self.ret(tail);
+            }
+            Expr::Var(name, pos) => {
+                self.load(name, *pos)?;
+                self.ret(tail);
+            }
+            Expr::Constr(name, pos) => {
+                let load = self.exception(name, *pos)?;
+                self.emit(load);
+                self.ret(tail);
+            }
+            Expr::App(f, args) => self.application(f, args, tail)?,
+            Expr::Fun(params, body) => {
+                self.function(None, params, body)?;
+                self.ret(tail);
+            }
+            Expr::Let(rec, pat, value, body, at) => {
+                let locals = self.current().locals.len();
+                let bound = self.bind(*rec, pat, value, *at)?;
+                self.expr(body, tail)?;
+                let count = self.current().locals.len() - locals;
+                self.forget_locals(count);
+                self.pops(bound, tail);
+            }
+            Expr::If(cond, then, otherwise) => {
+                let l_else = self.new_label();
+                self.expr(cond, false)?;
+                self.emit(Inst::BranchIfNot(l_else.clone()));
+                self.expr(then, tail)?;
+                if tail {
+                    self.place(&l_else);
+                    self.expr(otherwise, tail)?;
+                } else {
+                    let l_end = self.new_label();
+                    self.emit(Inst::Branch(l_end.clone()));
+                    self.place(&l_else);
+                    self.expr(otherwise, tail)?;
+                    self.place(&l_end);
+                }
+            }
+            Expr::BinOp(op, a, b) => {
+                self.expr(b, false)?;
+                self.emit(Inst::Push);
+                self.expr(a, false)?;
+                let prim = match op {
+                    BinOp::Add => "+",
+                    BinOp::Sub => "-",
+                    BinOp::Mul => "*",
+                    BinOp::Div => "/",
+                    BinOp::Mod => "mod",
+                    BinOp::Land => "land",
+                    BinOp::Lor => "lor",
+                    BinOp::Lxor => "lxor",
+                    BinOp::Lsl => "lsl",
+                    BinOp::Lsr => "lsr",
+                    BinOp::Asr => "asr",
+                    BinOp::Eq => "=",
+                    BinOp::Neq => "<>",
+                    BinOp::PhysEq => "==",
+                    BinOp::PhysNeq => "!=",
+                    BinOp::Lt => "<",
+                    BinOp::Gt => ">",
+                    BinOp::Le => "<=",
+                    BinOp::Ge => ">=",
+                    BinOp::Concat => "^",
+                    BinOp::FAdd => "+.",
+                    BinOp::FSub => "-.",
+                    BinOp::FMul => "*.",
+                    BinOp::FDiv => "/.",
+                    BinOp::Pow => "**",
+                };
+                self.prim(prim, 2);
+                self.ret(tail);
+            }
+            Expr::Neg(a) => {
+                self.expr(a, false)?;
+                self.prim("~-", 1);
+                self.ret(tail);
+            }
+            Expr::FNeg(a) => {
+                self.expr(a, false)?;
+                self.prim("~-.", 1);
+                self.ret(tail);
+            }
+            Expr::And(a, b) => {
+                let cond = Expr::If(a.clone(), b.clone(), Box::new(Expr::Int(0)));
+                self.expr(&cond, tail)?;
+            }
+            Expr::Or(a, b) => {
+                let cond = Expr::If(a.clone(), Box::new(Expr::Int(1)), b.clone());
+                self.expr(&cond, tail)?;
+            }
+            Expr::Tuple(elems) | Expr::Array(elems) => {
+                self.block(elems)?;
+                self.ret(tail);
+            }
+            Expr::Nil => {
+                self.emit(Inst::Const(0));
+                self.ret(tail);
+            }
+            Expr::Cons(head, tail_list) => {
+                self.block(&[(**head).clone(), (**tail_list).clone()])?;
+                self.ret(tail);
+            }
+            Expr::Get(a, i) => {
+                self.expr(i, false)?;
+                self.emit(Inst::Push);
+                self.expr(a, false)?;
+                self.emit(Inst::Getvectitem);
+                self.current().sz -= 1;
+                self.ret(tail);
+            }
+            Expr::Set(a, i, v) => {
+                self.expr(v, false)?;
+                self.emit(Inst::Push);
+                self.expr(i, false)?;
+                self.emit(Inst::Push);
+                self.expr(a, false)?;
+                self.emit(Inst::SetVectitem);
+                self.current().sz -= 2;
+                self.ret(tail);
+            }
+            Expr::Deref(r) => {
+                self.expr(r, false)?;
+                self.emit(Inst::Getfield(0));
+                self.ret(tail);
+            }
+            Expr::Assign(r, v) => {
+                self.expr(v, false)?;
+                self.emit(Inst::Push);
+                self.expr(r, false)?;
+                self.emit(Inst::Setfield(0));
+                self.current().sz -= 1;
+                self.ret(tail);
+            }
+            Expr::Seq(a, b) => {
+                self.expr(a, false)?;
+                self.expr(b, tail)?;
+            }
+            Expr::Match(scrutinee, cases) => {
+                self.expr(scrutinee, false)?;
+                self.emit(Inst::Push);
+                let slot = self.current().sz - 1;
+                self.cases(slot, cases, tail, match_failure())?;
+                self.pops(1, tail);
+            }
+            Expr::Try(body, cases) => {
+                let l_handler = self.new_label();
+                let l_end = self.new_label();
+                self.emit(Inst::PushTrap(l_handler.clone()));
+                self.current().sz += 4;
+                self.expr(body, false)?;
+                self.emit(Inst::PopTrap);
+                self.current().sz -= 4;
+                self.emit(Inst::Branch(l_end.clone()));
+                self.place(&l_handler);
+                self.emit(Inst::Push);
+                let slot = self.current().sz - 1;
+                //sans motif correspondant l'exception est relancée
+                self.cases(slot, cases, false, Inst::Acc(0))?;
+                self.pops(1, false);
+                self.place(&l_end);
+                self.ret(tail);
+            }
+            Expr::For(var, start, end, up, body) => {
+                let l_loop = self.new_label();
+                let l_end = self.new_label();
+                self.expr(start, false)?;
+                self.push_local(var);
+                self.expr(end, false)?;
+                self.push_local("limite de boucle");
+                self.place(&l_loop);
+                self.emit(Inst::Acc(0));
+                self.emit(Inst::Push);
+                self.emit(Inst::Acc(2));
+                self.prim(if *up { "<=" } else { ">=" }, 2);
+                self.emit(Inst::BranchIfNot(l_end.clone()));
+                self.expr(body, false)?;
+                self.emit(Inst::Const(1));
+                self.emit(Inst::Push);
+                self.emit(Inst::Acc(2));
+                self.prim(if *up { "+" } else { "-" }, 2);
+                self.emit(Inst::Assign(1));
+                self.emit(Inst::Branch(l_loop));
+                self.place(&l_end);
+                self.forget_locals(2);
+                self.pops(2, false);
+                self.emit(Inst::Const(0));
+                self.ret(tail);
+            }
+            Expr::While(cond, body) => {
+                let l_loop = self.new_label();
+                let l_end = self.new_label();
+                self.place(&l_loop);
+                self.expr(cond, false)?;
+                self.emit(Inst::BranchIfNot(l_end.clone()));
+                self.expr(body, false)?;
+                self.emit(Inst::Branch(l_loop));
+                self.place(&l_end);
+                self.emit(Inst::Const(0));
+                self.ret(tail);
+            }
+        }
+        Ok(())
+    }
+
+    //une primitive consomme ses arguments sauf l'accu
+    fn prim(&mut self, name: &str, arity: usize) {
+        self.emit(Inst::Prim(name.to_string()));
+        self.current().sz -= arity - 1;
+    }
+
+    //instruction qui charge l'exception dans l'accu
+    fn exception(&self, name: &str, pos: Pos) -> Result<Inst, CompileError> {
+        match self.exceptions.get(name) {
+            Some(id) => Ok(Inst::Const(*id)),
+            None if PREDEFINED_EXCEPTIONS.contains(&name) => Ok(Inst::Prim(name.to_string())),
+            None => Err(CompileError::new(
+                pos,
+                &format!("exception inconnue {}", name),
+            )),
+        }
+    }
+
+    //les éléments sont empilés à l'envers, le premier finit dans l'accu
+    fn block(&mut self, elems: &[Expr]) -> Result<(), CompileError> {
+        if elems.is_empty() {
+            self.emit(Inst::Makeblock(0, 0));
+            return Ok(());
+        }
+        for e in elems.iter().skip(1).rev() {
+            self.expr(e, false)?;
+            self.emit(Inst::Push);
+        }
+        self.expr(&elems[0], false)?;
+        self.emit(Inst::Makeblock(elems.len() as i64, 0));
+        self.current().sz -= elems.len() - 1;
+        Ok(())
+    }
+
+    fn application(&mut self, f: &Expr, args: &[Expr], tail: bool) -> Result<(), CompileError> {
+        if let Expr::Var(name, _) = f {
+            let arity = PRIMITIVES.iter().find(|(p, _)| p == name).map(|(_, a)| *a);
+            if let (Some(arity), None) = (arity, self.lookup(name)) {
+                if arity == args.len() {
+                    return self.primitive(name, &args[0], tail);
+                }
+            }
+        }
+        for a in args.iter().rev() {
+            self.expr(a, false)?;
+            self.emit(Inst::Push);
+        }
+        self.expr(f, false)?;
+        let n = args.len();
+        if tail {
+            let sz = self.current().sz;
+            self.emit(Inst::AppTerm(n as i64, sz as i64));
+        } else {
+            self.emit(Inst::Apply(n as i64));
+        }
+        self.current().sz -= n;
+        Ok(())
+    }
+
+    fn primitive(&mut self, name: &str, arg: &Expr, tail: bool) -> Result<(), CompileError> {
+        self.expr(arg, false)?;
+        match name {
+            "print_char" => {
+                self.prim("print", 1);
+                self.emit(Inst::Const(0));
+            }
+            "not" => self.prim("not", 1),
+            "abs" => self.prim("abs", 1),
+            "ref" => self.emit(Inst::Makeblock(1, 0)),
+            "raise" => {
+                self.emit(Inst::Raise);
+                return Ok(());
+            }
+            "fst" => self.emit(Inst::Getfield(0)),
+            "snd" => self.emit(Inst::Getfield(1)),
+            "ignore" => self.emit(Inst::Const(0)),
+            "Array.length" => self.emit(Inst::Veclength),
+            "String.length" => self.prim("string_length", 1),
+            "print_string" | "string_of_int" | "int_of_string" | "float_of_int"
+            | "int_of_float" | "sqrt" | "exp" | "log" | "sin" | "cos" | "string_of_float"
+            | "print_float" => self.prim(name, 1),
+            "Hashtbl.hash" => self.prim("hash", 1),
+            _ => unreachable!(),
+        }
+        self.ret(tail);
+        Ok(())
+    }
+
+    //fonction fun x -> p x qui permet de passer une primitive en argument
+    fn wrapper(&mut self, name: &str) -> String {
+        if let Some(label) = self.wrappers.get(name) {
+            return label.clone();
+        }
+        let label = self.new_label();
+        self.wrappers.insert(name.to_string(), label.clone());
+        let mut f = Function::new(None);
+        f.sz = 1;
+        self.functions.push(f);
+        self.place(&label);
+        //l'argument reste dans la pile pendant l'appel à la primitive
+        let arg = Expr::Var("x".to_string(), (0, 0));
+        self.current().locals.push(("x".to_string(), 0));
+        self.primitive(name, &arg, true)
+            .expect("les primitives enveloppées prennent un argument");
+        let f = self.functions.pop().unwrap();
+        self.finished.extend(f.code);
+        label
+    }
+
+    //lie un motif à la valeur de l'expression, renvoie le nombre de cases empilées
+    fn bind(
+        &mut self,
+        rec: bool,
+        pat: &Pattern,
+        value: &Expr,
+        at: Pos,
+    ) -> Result<usize, CompileError> {
+        if rec {
+            return match (pat, value) {
+                (Pattern::Var(name), Expr::Fun(params, body)) => {
+                    self.function(Some(name), params, body)?;
+                    //CLOSUREREC empile la fermeture
+                    let f = self.current();
+                    f.sz += 1;
+                    let slot = f.sz - 1;
+                    f.locals.push((name.clone(), slot));
+                    Ok(1)
+                }
+                _ => Err(CompileError::new(
+                    at,
+                    "seules les fonctions peuvent être récursives",
+                )),
+            };
+        }
+        self.expr(value, false)?;
+        self.emit(Inst::Push);
+        let slot = self.current().sz - 1;
+        if !irrefutable(pat) {
+            let l_fail = self.new_label();
+            let l_ok = self.new_label();
+            self.test(pat, slot, &[], &l_fail)?;
+            self.emit(Inst::Branch(l_ok.clone()));
+            self.place(&l_fail);
+            self.emit(match_failure());
+            self.emit(Inst::Raise);
+            self.place(&l_ok);
+        }
+        Ok(self.bind_vars(pat, slot)? + 1)
+    }
+
+    fn function(
+        &mut self,
+        rec_name: Option<&String>,
+        params: &[Pattern],
+        body: &Expr,
+    ) -> Result<(), CompileError> {
+        let label = self.new_label();
+        let arity = params.len();
+        let mut f = Function::new(rec_name.cloned());
+        f.sz = arity;
+        self.functions.push(f);
+
+        if arity > 1 {
+            self.emit(Inst::Restart);
+        }
+        self.place(&label);
+        if arity > 1 {
+            self.emit(Inst::Grab((arity - 1) as i64));
+        }
+        //le premier argument est au sommet de la pile
+        let mut complex = Vec::new();
+        for (i, p) in params.iter().enumerate() {
+            let slot = arity - 1 - i;
+            match p {
+                Pattern::Var(name) => self.current().locals.push((name.clone(), slot)),
+                Pattern::Any => {}
+                _ => complex.push((p.clone(), slot)),
+            }
+        }
+        if complex.is_empty() {
+            self.expr(body, true)?;
+        } else {
+            //fun (a, b) -> e devient un match sur l'argument
+            let mut e = body.clone();
+            for (i, (p, slot)) in complex.iter().enumerate().rev() {
+                let name = format!("argument {}", i);
+                self.current().locals.push((name.clone(), *slot));
+                e = Expr::Match(Box::new(Expr::Var(name, (0, 0))), vec![(p.clone(), e)]);
+            }
+            self.expr(&e, true)?;
+        }
+
+        let f = self.functions.pop().unwrap();
+        self.finished.extend(f.code);
+
+        //les variables libres sont chargées dans l'ordre inverse
+        let n = f.free.len();
+        for name in f.free.iter().skip(1).rev() {
+            self.load(name, (0, 0))?;
+            self.emit(Inst::Push);
+        }
+        if let Some(first) = f.free.first() {
+            self.load(first, (0, 0))?;
+        }
+        if rec_name.is_some() {
+            self.emit(Inst::ClosureRec(label, n as i64));
+        } else {
+            self.emit(Inst::Closure(label, n as i64));
+        }
+        self.current().sz -= n.saturating_sub(1);
+        Ok(())
+    }
+
+    fn load_path(&mut self, slot: usize, path: &[usize]) {
+        let sz = self.current().sz;
+        self.emit(Inst::Acc((sz - slot - 1) as u64));
+        for i in path {
+            self.emit(Inst::Getfield(*i as i64));
+        }
+    }
+
+    //saute à fail si la valeur au chemin donné ne correspond pas au motif
+    fn test(
+        &mut self,
+        pat: &Pattern,
+        slot: usize,
+        path: &[usize],
+        fail: &str,
+    ) -> Result<(), CompileError> {
+        match pat {
+            Pattern::Any | Pattern::Var(_) => {}
+            Pattern::Int(n) => self.test_equal(Inst::Const(*n), slot, path, fail),
+            Pattern::Constr(name, pos) => {
+                let load = self.exception(name, *pos)?;
+                self.test_equal(load, slot, path, fail);
+            }
+            Pattern::Nil => {
+                let l_ok = self.new_label();
+                self.load_path(slot, path);
+                self.emit(Inst::BranchIfNot(l_ok.clone()));
+                self.emit(Inst::Branch(fail.to_string()));
+                self.place(&l_ok);
+            }
+            Pattern::Cons(head, tail) => {
+                self.load_path(slot, path);
+                self.emit(Inst::BranchIfNot(fail.to_string()));
+                self.test(head, slot, &[path, &[0]].concat(), fail)?;
+                self.test(tail, slot, &[path, &[1]].concat(), fail)?;
+            }
+            Pattern::Tuple(elems) => {
+                for (i, p) in elems.iter().enumerate() {
+                    self.test(p, slot, &[path, &[i]].concat(), fail)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //saute à fail si la valeur au chemin donné diffère de celle chargée par load
+    fn test_equal(&mut self, load: Inst, slot: usize, path: &[usize], fail: &str) {
+        self.emit(load);
+        self.emit(Inst::Push);
+        self.load_path(slot, path);
+        self.prim("=", 2);
+        self.emit(Inst::BranchIfNot(fail.to_string()));
+    }
+
+    //empile les variables du motif, renvoie leur nombre
+    fn bind_vars(&mut self, pat: &Pattern, slot: usize) -> Result<usize, CompileError> {
+        //une variable seule est déjà dans sa case
+        if let Pattern::Var(name) = pat {
+            self.current().locals.push((name.clone(), slot));
+            return Ok(0);
+        }
+        let mut vars = Vec::new();
+        collect_vars(pat, &mut Vec::new(), &mut vars);
+        for (name, path) in &vars {
+            self.load_path(slot, path);
+            self.push_local(name);
+        }
+        Ok(vars.len())
+    }
+
+    fn cases(
+        &mut self,
+        slot: usize,
+        cases: &[(Pattern, Expr)],
+        tail: bool,
+        otherwise: Inst,
+    ) -> Result<(), CompileError> {
+        let l_end = self.new_label();
+        for (pat, body) in cases {
+            let l_next = self.new_label();
+            self.test(pat, slot, &[], &l_next)?;
+            let locals = self.current().locals.len();
+            let bound = self.bind_vars(pat, slot)?;
+            self.expr(body, tail)?;
+            let count = self.current().locals.len() - locals;
+            self.forget_locals(count);
+            self.pops(bound, tail);
+            if !tail {
+                self.emit(Inst::Branch(l_end.clone()));
+            }
+            self.place(&l_next);
+        }
+        self.emit(otherwise);
+        self.emit(Inst::Raise);
+        if !tail {
+            self.place(&l_end);
+        }
+        Ok(())
+    }
+}
+
+//levée quand aucun motif ne correspond, même si le programme déclare une
+//exception du même nom
+fn match_failure() -> Inst {
+    Inst::Prim("Match_failure".to_string())
+}
+
+fn irrefutable(pat: &Pattern) -> bool {
+    match pat {
+        Pattern::Any | Pattern::Var(_) => true,
+        Pattern::Tuple(elems) => elems.iter().all(irrefutable),
+        _ => false,
+    }
+}
+
+fn collect_vars(pat: &Pattern, path: &mut Vec<usize>, vars: &mut Vec<(String, Vec<usize>)>) {
+    match pat {
+        Pattern::Var(name) => vars.push((name.clone(), path.clone())),
+        Pattern::Cons(head, tail) => {
+            for (i, p) in [head, tail].iter().enumerate() {
+                path.push(i);
+                collect_vars(p, path, vars);
+                path.pop();
+            }
+        }
+        Pattern::Tuple(elems) => {
+            for (i, p) in elems.iter().enumerate() {
+                path.push(i);
+                collect_vars(p, path, vars);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+//attache chaque label à l'instruction qui le suit, plusieurs labels
+//sur une même instruction sont fusionnés
+fn link(items: Vec<Code>) -> Vec<(Option<String>, Inst)> {
+    let mut prog: Vec<(Option<String>, Inst)> = Vec::new();
+    let mut aliases: HashMap<String, String> = HashMap::new();
+    let mut pending: Option<String> = None;
+
+    for item in items {
+        match item {
+            Code::Label(l) => match &pending {
+                Some(p) => {
+                    aliases.insert(l, p.clone());
+                }
+                None => pending = Some(l),
+            },
+            Code::Inst(inst) => prog.push((pending.take(), inst)),
+        }
+    }
+
+    for (_, inst) in prog.iter_mut() {
+        for l in inst.targets_mut() {
+            if let Some(alias) = aliases.get(l) {
+                *l = alias.clone();
+            }
+        }
+    }
+    prog
+}
@@ -0,0 +1,234 @@
+use crate::compiler::CompileError;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Token {
+    Int(i64),
+    Float(f64),
+    Char(i64),
+    Str(String),
+    //identifiant en minuscule (variables, fonctions)
+    Ident(String),
+    //identifiant en majuscule (exceptions, modules)
+    UIdent(String),
+    Keyword(&'static str),
+    Symbol(&'static str),
+    Eof,
+}
+
+//ligne et colonne à partir de 1
+pub type Pos = (usize, usize);
+
+const KEYWORDS: [&str; 30] = [
+    "let",
+    "rec",
+    "in",
+    "fun",
+    "function",
+    "if",
+    "then",
+    "else",
+    "match",
+    "with",
+    "try",
+    "raise",
+    "exception",
+    "true",
+    "false",
+    "begin",
+    "end",
+    "for",
+    "to",
+    "downto",
+    "do",
+    "done",
+    "while",
+    "mod",
+    "land",
+    "lor",
+    "lxor",
+    "lsl",
+    "lsr",
+    "asr",
+];
+
+//du plus long au plus court pour que la recherche soit gloutonne
+const SYMBOLS: [&str; 37] = [
+    ";;", "[|", "|]", "->", "::", ":=", "<-", "<=", ">=", "<>", "==", "!=", "&&", "||", "+.", "-.",
+    "*.", "/.", "**", "(", ")", "[", "]", ";", ",", "|", "=", "<", ">", "+", "-", "*", "/", "!",
+    ".", "_", "^",
+];
+
+pub fn tokenize(source: &str) -> Result<Vec<(Token, Pos)>, CompileError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let mut line = 1;
+    let mut col = 1;
+
+    //avance d'un caractère en tenant à jour la position
+    let advance = |i: &mut usize, line: &mut usize, col: &mut usize| {
+        if chars[*i] == '\n' {
+            *line += 1;
+            *col = 1;
+        } else {
+            *col += 1;
+        }
+        *i += 1;
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let pos = (line, col);
+
+        if c.is_whitespace() {
+            advance(&mut i, &mut line, &mut col);
+        } else if c == '(' && chars.get(i + 1) == Some(&'*') {
+            //commentaires imbriqués
+            let mut depth = 0;
+            loop {
+                if i >= chars.len() {
+                    return Err(CompileError::new(pos, "commentaire non terminé"));
+                }
+                if chars[i] == '(' && chars.get(i + 1) == Some(&'*') {
+                    depth += 1;
+                    advance(&mut i, &mut line, &mut col);
+                } else if chars[i] == '*' && chars.get(i + 1) == Some(&')') {
+                    depth -= 1;
+                    advance(&mut i, &mut line, &mut col);
+                    if depth == 0 {
+                        advance(&mut i, &mut line, &mut col);
+                        break;
+                    }
+                }
+                advance(&mut i, &mut line, &mut col);
+            }
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '_') {
+                advance(&mut i, &mut line, &mut col);
+            }
+            //flottant : partie décimale ou exposant après les chiffres
+            let mut float = false;
+            if chars.get(i) == Some(&'.') {
+                float = true;
+                advance(&mut i, &mut line, &mut col);
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '_') {
+                    advance(&mut i, &mut line, &mut col);
+                }
+            }
+            if matches!(chars.get(i), Some('e') | Some('E')) {
+                let sign = matches!(chars.get(i + 1), Some('+') | Some('-')) as usize;
+                if chars.get(i + 1 + sign).is_some_and(|c| c.is_ascii_digit()) {
+                    float = true;
+                    for _ in 0..=sign {
+                        advance(&mut i, &mut line, &mut col);
+                    }
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        advance(&mut i, &mut line, &mut col);
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().filter(|c| **c != '_').collect();
+            if float {
+                //le format de Rust accepte aussi « 1. » et « 1e5 »
+                tokens.push((Token::Float(text.parse().unwrap()), pos));
+            } else {
+                match text.parse::<i64>() {
+                    Ok(n) => tokens.push((Token::Int(n), pos)),
+                    Err(_) => return Err(CompileError::new(pos, "entier trop grand")),
+                }
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '\'')
+            {
+                advance(&mut i, &mut line, &mut col);
+            }
+            let word: String = chars[start..i].iter().collect();
+            let token = if word == "_" {
+                Token::Symbol("_")
+            } else if let Some(k) = KEYWORDS.iter().find(|k| **k == word) {
+                Token::Keyword(k)
+            } else if c.is_uppercase() {
+                Token::UIdent(word)
+            } else {
+                Token::Ident(word)
+            };
+            tokens.push((token, pos));
+        } else if c == '\'' {
+            let (value, len) = match (chars.get(i + 1), chars.get(i + 2), chars.get(i + 3)) {
+                (Some('\\'), Some(e), Some('\'')) => match e {
+                    'n' => ('\n', 4),
+                    't' => ('\t', 4),
+                    '\\' => ('\\', 4),
+                    '\'' => ('\'', 4),
+                    _ => return Err(CompileError::new(pos, "caractère d'échappement inconnu")),
+                },
+                (Some(v), Some('\''), _) => (*v, 3),
+                _ => return Err(CompileError::new(pos, "caractère mal formé")),
+            };
+            for _ in 0..len {
+                advance(&mut i, &mut line, &mut col);
+            }
+            tokens.push((Token::Char(value as i64), pos));
+        } else if c == '"' {
+            advance(&mut i, &mut line, &mut col);
+            let mut bytes = Vec::new();
+            loop {
+                let c = match chars.get(i) {
+                    Some(c) => *c,
+                    None => return Err(CompileError::new(pos, "chaîne non terminée")),
+                };
+                advance(&mut i, &mut line, &mut col);
+                match c {
+                    '"' => break,
+                    '\\' => {
+                        let e = match chars.get(i) {
+                            Some(e) => *e,
+                            None => return Err(CompileError::new(pos, "chaîne non terminée")),
+                        };
+                        advance(&mut i, &mut line, &mut col);
+                        match e {
+                            'n' => bytes.push(b'\n'),
+                            't' => bytes.push(b'\t'),
+                            '\\' => bytes.push(b'\\'),
+                            '"' => bytes.push(b'"'),
+                            _ => {
+                                return Err(CompileError::new(
+                                    pos,
+                                    "caractère d'échappement inconnu",
+                                ))
+                            }
+                        }
+                    }
+                    c => {
+                        let mut buf = [0; 4];
+                        bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    }
+                }
+            }
+            let text = String::from_utf8(bytes).expect("la source est en UTF-8");
+            tokens.push((Token::Str(text), pos));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            match SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+                Some(s) => {
+                    for _ in 0..s.len() {
+                        advance(&mut i, &mut line, &mut col);
+                    }
+                    tokens.push((Token::Symbol(s), pos));
+                }
+                None => {
+                    return Err(CompileError::new(
+                        pos,
+                        &format!("caractère inattendu `{}`", c),
+                    ))
+                }
+            }
+        }
+    }
+
+    tokens.push((Token::Eof, (line, col)));
+    Ok(tokens)
+}
@@ -0,0 +1,39 @@
+//compilateur du sous-ensemble de mini-ML des tests vers le bytecode de la ZAM
+mod codegen;
+mod lexer;
+mod syntax;
+
+use std::fmt;
+
+use crate::machine::Inst;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CompileError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl CompileError {
+    fn new(pos: lexer::Pos, message: &str) -> CompileError {
+        CompileError {
+            line: pos.0,
+            column: pos.1,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+pub fn compile(source: &str) -> Result<Vec<(Option<String>, Inst)>, CompileError> {
+    let tokens = lexer::tokenize(source)?;
+    let items = syntax::Parser::new(tokens).program()?;
+    codegen::Generator::new().program(&items)
+}
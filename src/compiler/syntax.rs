@@ -0,0 +1,741 @@
+use crate::compiler::lexer::{Pos, Token};
+use crate::compiler::CompileError;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Land,
+    Lor,
+    Lxor,
+    Lsl,
+    Lsr,
+    Asr,
+    Eq,
+    Neq,
+    PhysEq,
+    PhysNeq,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Concat,
+    FAdd,
+    FSub,
+    FMul,
+    FDiv,
+    Pow,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    Any,
+    Var(String),
+    Int(i64),
+    Nil,
+    Cons(Box<Pattern>, Box<Pattern>),
+    Tuple(Vec<Pattern>),
+    //exception sans argument
+    Constr(String, Pos),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expr {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Var(String, Pos),
+    Constr(String, Pos),
+    App(Box<Expr>, Vec<Expr>),
+    Fun(Vec<Pattern>, Box<Expr>),
+    //position de la liaison
+    Let(bool, Pattern, Box<Expr>, Box<Expr>, Pos),
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+    BinOp(BinOp, Box<Expr>, Box<Expr>),
+    Neg(Box<Expr>),
+    FNeg(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Tuple(Vec<Expr>),
+    Nil,
+    Cons(Box<Expr>, Box<Expr>),
+    Array(Vec<Expr>),
+    Get(Box<Expr>, Box<Expr>),
+    Set(Box<Expr>, Box<Expr>, Box<Expr>),
+    Deref(Box<Expr>),
+    Assign(Box<Expr>, Box<Expr>),
+    Seq(Box<Expr>, Box<Expr>),
+    Match(Box<Expr>, Vec<(Pattern, Expr)>),
+    Try(Box<Expr>, Vec<(Pattern, Expr)>),
+    //variable, début, fin, croissant, corps
+    For(String, Box<Expr>, Box<Expr>, bool, Box<Expr>),
+    While(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Item {
+    Let(bool, Pattern, Expr, Pos),
+    Exception(String),
+    Expr(Expr),
+}
+
+pub struct Parser {
+    tokens: Vec<(Token, Pos)>,
+    pos: usize,
+}
+
+fn unit() -> Expr {
+    Expr::Int(0)
+}
+
+impl Parser {
+    pub fn new(tokens: Vec<(Token, Pos)>) -> Parser {
+        Parser { tokens, pos: 0 }
+    }
+
+    //au-delà de la fin on reste sur Eof, pos compte tous les lexèmes lus pour
+    //qu'un retour en arrière après Eof y revienne
+    fn current(&self) -> &(Token, Pos) {
+        &self.tokens[self.pos.min(self.tokens.len() - 1)]
+    }
+
+    fn peek(&self) -> &Token {
+        &self.current().0
+    }
+
+    fn peek_at(&self, n: usize) -> &Token {
+        let i = (self.pos + n).min(self.tokens.len() - 1);
+        &self.tokens[i].0
+    }
+
+    fn position(&self) -> Pos {
+        self.current().1
+    }
+
+    fn advance(&mut self) -> Token {
+        let tok = self.peek().clone();
+        self.pos += 1;
+        tok
+    }
+
+    fn is_symbol(&self, s: &str) -> bool {
+        matches!(self.peek(), Token::Symbol(sym) if *sym == s)
+    }
+
+    fn is_keyword(&self, k: &str) -> bool {
+        matches!(self.peek(), Token::Keyword(kw) if *kw == k)
+    }
+
+    fn accept_symbol(&mut self, s: &str) -> bool {
+        if self.is_symbol(s) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn accept_keyword(&mut self, k: &str) -> bool {
+        if self.is_keyword(k) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, CompileError> {
+        let found = match self.peek() {
+            Token::Int(n) => n.to_string(),
+            Token::Float(x) => x.to_string(),
+            Token::Char(c) => format!("'{}'", (*c as u8) as char),
+            Token::Str(s) => format!("{:?}", s),
+            Token::Ident(s) | Token::UIdent(s) => s.clone(),
+            Token::Keyword(s) | Token::Symbol(s) => s.to_string(),
+            Token::Eof => "fin de fichier".to_string(),
+        };
+        Err(CompileError::new(
+            self.position(),
+            &format!("`{}` trouvé, {} attendu", found, expected),
+        ))
+    }
+
+    fn expect_symbol(&mut self, s: &str) -> Result<(), CompileError> {
+        if self.accept_symbol(s) {
+            Ok(())
+        } else {
+            self.error(&format!("`{}`", s))
+        }
+    }
+
+    fn expect_keyword(&mut self, k: &str) -> Result<(), CompileError> {
+        if self.accept_keyword(k) {
+            Ok(())
+        } else {
+            self.error(&format!("`{}`", k))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, CompileError> {
+        match self.peek().clone() {
+            Token::Ident(s) => {
+                self.advance();
+                Ok(s)
+            }
+            _ => self.error("un identifiant"),
+        }
+    }
+
+    pub fn program(&mut self) -> Result<Vec<Item>, CompileError> {
+        let mut items = Vec::new();
+        while *self.peek() != Token::Eof {
+            if self.accept_symbol(";;") {
+                continue;
+            }
+            if self.accept_keyword("exception") {
+                match self.advance() {
+                    Token::UIdent(name) => items.push(Item::Exception(name)),
+                    _ => {
+                        self.pos -= 1;
+                        return self.error("un nom d'exception");
+                    }
+                }
+            } else if self.is_keyword("let") {
+                let start = self.pos;
+                self.advance();
+                let rec = self.accept_keyword("rec");
+                let at = self.position();
+                let (pat, value) = self.binding()?;
+                if self.is_keyword("in") {
+                    //c'est une expression let ... in
+                    self.pos = start;
+                    items.push(Item::Expr(self.expr()?));
+                } else {
+                    items.push(Item::Let(rec, pat, value, at));
+                }
+            } else {
+                items.push(Item::Expr(self.expr()?));
+            }
+        }
+        Ok(items)
+    }
+
+    //pattern = expr ou f args = expr
+    fn binding(&mut self) -> Result<(Pattern, Expr), CompileError> {
+        let pat = self.pattern_atom()?;
+        let mut args = Vec::new();
+        while !self.is_symbol("=") {
+            args.push(self.pattern_atom()?);
+        }
+        self.expect_symbol("=")?;
+        let body = self.expr()?;
+        if args.is_empty() {
+            Ok((pat, body))
+        } else {
+            match pat {
+                Pattern::Var(_) => Ok((pat, Expr::Fun(args, Box::new(body)))),
+                _ => self.error("un nom de fonction"),
+            }
+        }
+    }
+
+    pub fn expr(&mut self) -> Result<Expr, CompileError> {
+        let first = self.stmt()?;
+        //un ; final avant done, end ou ) est toléré
+        if self.accept_symbol(";") && self.starts_expr() {
+            let rest = self.expr()?;
+            return Ok(Expr::Seq(Box::new(first), Box::new(rest)));
+        }
+        Ok(first)
+    }
+
+    fn starts_expr(&self) -> bool {
+        match self.peek() {
+            Token::Int(_)
+            | Token::Float(_)
+            | Token::Char(_)
+            | Token::Str(_)
+            | Token::Ident(_)
+            | Token::UIdent(_) => true,
+            Token::Keyword(k) => matches!(
+                *k,
+                "let"
+                    | "fun"
+                    | "function"
+                    | "if"
+                    | "match"
+                    | "try"
+                    | "raise"
+                    | "true"
+                    | "false"
+                    | "begin"
+                    | "for"
+                    | "while"
+            ),
+            Token::Symbol(s) => matches!(*s, "(" | "[" | "[|" | "-" | "!"),
+            Token::Eof => false,
+        }
+    }
+
+    //expressions qui s'étendent le plus loin possible à droite
+    fn stmt(&mut self) -> Result<Expr, CompileError> {
+        if self.accept_keyword("let") {
+            let rec = self.accept_keyword("rec");
+            let at = self.position();
+            let (pat, value) = self.binding()?;
+            self.expect_keyword("in")?;
+            let body = self.expr()?;
+            return Ok(Expr::Let(rec, pat, Box::new(value), Box::new(body), at));
+        }
+        if self.accept_keyword("fun") {
+            let mut args = vec![self.pattern_atom()?];
+            while !self.is_symbol("->") {
+                args.push(self.pattern_atom()?);
+            }
+            self.expect_symbol("->")?;
+            let body = self.expr()?;
+            return Ok(Expr::Fun(args, Box::new(body)));
+        }
+        if self.accept_keyword("function") {
+            let cases = self.cases()?;
+            let arg = "arg function".to_string();
+            let pos = self.position();
+            let body = Expr::Match(Box::new(Expr::Var(arg.clone(), pos)), cases);
+            return Ok(Expr::Fun(vec![Pattern::Var(arg)], Box::new(body)));
+        }
+        if self.accept_keyword("match") {
+            let e = self.expr()?;
+            self.expect_keyword("with")?;
+            let cases = self.cases()?;
+            return Ok(Expr::Match(Box::new(e), cases));
+        }
+        if self.accept_keyword("try") {
+            let e = self.expr()?;
+            self.expect_keyword("with")?;
+            let cases = self.cases()?;
+            return Ok(Expr::Try(Box::new(e), cases));
+        }
+        self.assign()
+    }
+
+    fn cases(&mut self) -> Result<Vec<(Pattern, Expr)>, CompileError> {
+        let mut cases = Vec::new();
+        self.accept_symbol("|");
+        loop {
+            let pat = self.pattern()?;
+            self.expect_symbol("->")?;
+            let body = self.expr()?;
+            cases.push((pat, body));
+            if !self.accept_symbol("|") {
+                return Ok(cases);
+            }
+        }
+    }
+
+    fn if_expr(&mut self) -> Result<Expr, CompileError> {
+        let cond = self.expr()?;
+        self.expect_keyword("then")?;
+        let then = self.branch()?;
+        let otherwise = if self.accept_keyword("else") {
+            self.branch()?
+        } else {
+            unit()
+        };
+        Ok(Expr::If(
+            Box::new(cond),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    //une branche de if ne contient pas de séquence
+    fn branch(&mut self) -> Result<Expr, CompileError> {
+        match self.peek() {
+            Token::Keyword("let")
+            | Token::Keyword("fun")
+            | Token::Keyword("function")
+            | Token::Keyword("match")
+            | Token::Keyword("try") => self.stmt(),
+            _ => self.assign(),
+        }
+    }
+
+    fn assign(&mut self) -> Result<Expr, CompileError> {
+        let left = self.tuple()?;
+        if self.accept_symbol("<-") {
+            let value = self.branch()?;
+            return match left {
+                Expr::Get(a, i) => Ok(Expr::Set(a, i, Box::new(value))),
+                _ => self.error("une case de tableau à gauche de `<-`"),
+            };
+        }
+        if self.accept_symbol(":=") {
+            let value = self.branch()?;
+            return Ok(Expr::Assign(Box::new(left), Box::new(value)));
+        }
+        Ok(left)
+    }
+
+    fn tuple(&mut self) -> Result<Expr, CompileError> {
+        let first = self.or()?;
+        if !self.is_symbol(",") {
+            return Ok(first);
+        }
+        let mut elems = vec![first];
+        while self.accept_symbol(",") {
+            elems.push(self.or()?);
+        }
+        Ok(Expr::Tuple(elems))
+    }
+
+    fn or(&mut self) -> Result<Expr, CompileError> {
+        let left = self.and()?;
+        if self.accept_symbol("||") {
+            let right = self.or()?;
+            return Ok(Expr::Or(Box::new(left), Box::new(right)));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, CompileError> {
+        let left = self.comparison()?;
+        if self.accept_symbol("&&") {
+            let right = self.and()?;
+            return Ok(Expr::And(Box::new(left), Box::new(right)));
+        }
+        Ok(left)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, CompileError> {
+        let mut left = self.concat()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol("=") => BinOp::Eq,
+                Token::Symbol("<>") => BinOp::Neq,
+                Token::Symbol("==") => BinOp::PhysEq,
+                Token::Symbol("!=") => BinOp::PhysNeq,
+                Token::Symbol("<") => BinOp::Lt,
+                Token::Symbol(">") => BinOp::Gt,
+                Token::Symbol("<=") => BinOp::Le,
+                Token::Symbol(">=") => BinOp::Ge,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let right = self.concat()?;
+            left = Expr::BinOp(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    //associatif à droite comme ::
+    fn concat(&mut self) -> Result<Expr, CompileError> {
+        let left = self.cons()?;
+        if self.accept_symbol("^") {
+            let right = self.concat()?;
+            return Ok(Expr::BinOp(BinOp::Concat, Box::new(left), Box::new(right)));
+        }
+        Ok(left)
+    }
+
+    fn cons(&mut self) -> Result<Expr, CompileError> {
+        let head = self.additive()?;
+        if self.accept_symbol("::") {
+            let tail = self.cons()?;
+            return Ok(Expr::Cons(Box::new(head), Box::new(tail)));
+        }
+        Ok(head)
+    }
+
+    fn additive(&mut self) -> Result<Expr, CompileError> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol("+") => BinOp::Add,
+                Token::Symbol("-") => BinOp::Sub,
+                Token::Symbol("+.") => BinOp::FAdd,
+                Token::Symbol("-.") => BinOp::FSub,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let right = self.multiplicative()?;
+            left = Expr::BinOp(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, CompileError> {
+        let mut left = self.shift()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol("*") => BinOp::Mul,
+                Token::Symbol("/") => BinOp::Div,
+                Token::Symbol("*.") => BinOp::FMul,
+                Token::Symbol("/.") => BinOp::FDiv,
+                Token::Keyword("mod") => BinOp::Mod,
+                Token::Keyword("land") => BinOp::Land,
+                Token::Keyword("lor") => BinOp::Lor,
+                Token::Keyword("lxor") => BinOp::Lxor,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let right = self.shift()?;
+            left = Expr::BinOp(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    //lsl, lsr et asr associent à droite
+    fn shift(&mut self) -> Result<Expr, CompileError> {
+        let left = self.unary()?;
+        let op = match self.peek() {
+            Token::Keyword("lsl") => BinOp::Lsl,
+            Token::Keyword("lsr") => BinOp::Lsr,
+            Token::Keyword("asr") => BinOp::Asr,
+            Token::Symbol("**") => BinOp::Pow,
+            _ => return Ok(left),
+        };
+        self.advance();
+        let right = self.shift()?;
+        Ok(Expr::BinOp(op, Box::new(left), Box::new(right)))
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        if self.accept_symbol("-") {
+            return match self.unary()? {
+                Expr::Int(n) => Ok(Expr::Int(-n)),
+                Expr::Float(x) => Ok(Expr::Float(-x)),
+                e => Ok(Expr::Neg(Box::new(e))),
+            };
+        }
+        if self.accept_symbol("-.") {
+            return match self.unary()? {
+                Expr::Float(x) => Ok(Expr::Float(-x)),
+                e => Ok(Expr::FNeg(Box::new(e))),
+            };
+        }
+        match self.peek() {
+            Token::Keyword("let")
+            | Token::Keyword("fun")
+            | Token::Keyword("function")
+            | Token::Keyword("match")
+            | Token::Keyword("try") => self.stmt(),
+            _ => self.application(),
+        }
+    }
+
+    fn application(&mut self) -> Result<Expr, CompileError> {
+        if self.accept_keyword("raise") {
+            let pos = self.position();
+            let arg = self.prefix()?;
+            return Ok(Expr::App(
+                Box::new(Expr::Var("raise".to_string(), pos)),
+                vec![arg],
+            ));
+        }
+        let func = self.prefix()?;
+        let mut args = Vec::new();
+        while self.starts_argument() {
+            args.push(self.prefix()?);
+        }
+        if args.is_empty() {
+            Ok(func)
+        } else {
+            Ok(Expr::App(Box::new(func), args))
+        }
+    }
+
+    fn starts_argument(&self) -> bool {
+        match self.peek() {
+            Token::Int(_)
+            | Token::Float(_)
+            | Token::Char(_)
+            | Token::Str(_)
+            | Token::Ident(_)
+            | Token::UIdent(_) => true,
+            Token::Keyword(k) => matches!(*k, "true" | "false" | "begin"),
+            Token::Symbol(s) => matches!(*s, "(" | "[" | "[|" | "!"),
+            Token::Eof => false,
+        }
+    }
+
+    fn prefix(&mut self) -> Result<Expr, CompileError> {
+        if self.accept_symbol("!") {
+            let e = self.prefix()?;
+            return Ok(Expr::Deref(Box::new(e)));
+        }
+        let mut e = self.atom()?;
+        while self.is_symbol(".") && *self.peek_at(1) == Token::Symbol("(") {
+            self.advance();
+            self.advance();
+            let index = self.expr()?;
+            self.expect_symbol(")")?;
+            e = Expr::Get(Box::new(e), Box::new(index));
+        }
+        Ok(e)
+    }
+
+    fn atom(&mut self) -> Result<Expr, CompileError> {
+        let pos = self.position();
+        match self.advance() {
+            Token::Int(n) | Token::Char(n) => Ok(Expr::Int(n)),
+            Token::Float(x) => Ok(Expr::Float(x)),
+            Token::Str(s) => Ok(Expr::Str(s)),
+            Token::Ident(s) => Ok(Expr::Var(s, pos)),
+            Token::UIdent(m) => {
+                //fonction d'un module, par exemple Array.length
+                if self.is_symbol(".") {
+                    if let Token::Ident(f) = self.peek_at(1).clone() {
+                        self.advance();
+                        self.advance();
+                        return Ok(Expr::Var(format!("{}.{}", m, f), pos));
+                    }
+                }
+                Ok(Expr::Constr(m, pos))
+            }
+            Token::Keyword("true") => Ok(Expr::Int(1)),
+            Token::Keyword("false") => Ok(Expr::Int(0)),
+            Token::Keyword("begin") => {
+                if self.accept_keyword("end") {
+                    return Ok(unit());
+                }
+                let e = self.expr()?;
+                self.expect_keyword("end")?;
+                Ok(e)
+            }
+            Token::Keyword("if") => self.if_expr(),
+            Token::Keyword("for") => {
+                let var = self.ident()?;
+                self.expect_symbol("=")?;
+                let start = self.expr()?;
+                let up = if self.accept_keyword("to") {
+                    true
+                } else {
+                    self.expect_keyword("downto")?;
+                    false
+                };
+                let end = self.expr()?;
+                self.expect_keyword("do")?;
+                let body = self.loop_body()?;
+                Ok(Expr::For(
+                    var,
+                    Box::new(start),
+                    Box::new(end),
+                    up,
+                    Box::new(body),
+                ))
+            }
+            Token::Keyword("while") => {
+                let cond = self.expr()?;
+                self.expect_keyword("do")?;
+                let body = self.loop_body()?;
+                Ok(Expr::While(Box::new(cond), Box::new(body)))
+            }
+            Token::Symbol("(") => {
+                if self.accept_symbol(")") {
+                    return Ok(unit());
+                }
+                let e = self.expr()?;
+                self.expect_symbol(")")?;
+                Ok(e)
+            }
+            Token::Symbol("[") => {
+                let elems = self.elements("]")?;
+                Ok(elems.into_iter().rev().fold(Expr::Nil, |tail, head| {
+                    Expr::Cons(Box::new(head), Box::new(tail))
+                }))
+            }
+            Token::Symbol("[|") => Ok(Expr::Array(self.elements("|]")?)),
+            _ => {
+                self.pos -= 1;
+                self.error("une expression")
+            }
+        }
+    }
+
+    fn loop_body(&mut self) -> Result<Expr, CompileError> {
+        if self.accept_keyword("done") {
+            return Ok(unit());
+        }
+        let body = self.expr()?;
+        self.expect_keyword("done")?;
+        Ok(body)
+    }
+
+    //éléments séparés par des ; jusqu'au délimiteur fermant
+    fn elements(&mut self, close: &str) -> Result<Vec<Expr>, CompileError> {
+        let mut elems = Vec::new();
+        while !self.accept_symbol(close) {
+            elems.push(self.assign()?);
+            if !self.accept_symbol(";") {
+                self.expect_symbol(close)?;
+                break;
+            }
+        }
+        Ok(elems)
+    }
+
+    pub fn pattern(&mut self) -> Result<Pattern, CompileError> {
+        let first = self.pattern_cons()?;
+        if !self.is_symbol(",") {
+            return Ok(first);
+        }
+        let mut elems = vec![first];
+        while self.accept_symbol(",") {
+            elems.push(self.pattern_cons()?);
+        }
+        Ok(Pattern::Tuple(elems))
+    }
+
+    fn pattern_cons(&mut self) -> Result<Pattern, CompileError> {
+        let head = self.pattern_atom()?;
+        if self.accept_symbol("::") {
+            let tail = self.pattern_cons()?;
+            return Ok(Pattern::Cons(Box::new(head), Box::new(tail)));
+        }
+        Ok(head)
+    }
+
+    fn pattern_atom(&mut self) -> Result<Pattern, CompileError> {
+        let pos = self.position();
+        match self.advance() {
+            Token::Symbol("_") => Ok(Pattern::Any),
+            Token::Ident(s) => Ok(Pattern::Var(s)),
+            Token::Int(n) | Token::Char(n) => Ok(Pattern::Int(n)),
+            Token::Symbol("-") => match self.advance() {
+                Token::Int(n) => Ok(Pattern::Int(-n)),
+                _ => {
+                    self.pos -= 1;
+                    self.error("un entier")
+                }
+            },
+            Token::Keyword("true") => Ok(Pattern::Int(1)),
+            Token::Keyword("false") => Ok(Pattern::Int(0)),
+            Token::UIdent(s) => Ok(Pattern::Constr(s, pos)),
+            Token::Symbol("(") => {
+                if self.accept_symbol(")") {
+                    return Ok(Pattern::Int(0));
+                }
+                let p = self.pattern()?;
+                self.expect_symbol(")")?;
+                Ok(p)
+            }
+            Token::Symbol("[") => {
+                let mut elems = Vec::new();
+                while !self.accept_symbol("]") {
+                    elems.push(self.pattern_cons()?);
+                    if !self.accept_symbol(";") {
+                        self.expect_symbol("]")?;
+                        break;
+                    }
+                }
+                Ok(elems.into_iter().rev().fold(Pattern::Nil, |tail, head| {
+                    Pattern::Cons(Box::new(head), Box::new(tail))
+                }))
+            }
+            _ => {
+                self.pos -= 1;
+                self.error("un motif")
+            }
+        }
+    }
+}
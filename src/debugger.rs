@@ -0,0 +1,374 @@
+//débogueur interactif au-dessus de ZAM::step
+use crate::error::{format_backtrace, VmError};
+use crate::machine::{Inst, Outcome, ZAM};
+use crate::mlvalue::Mlvalue;
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "commandes :
+  break <label|pc>      point d'arrêt (b)
+  delete <label|pc|all> supprime un point d'arrêt, all supprime aussi
+                        les surveillances (d)
+  step [n]              exécute n instructions (s)
+  next                  comme step, mais passe par-dessus un APPLY (n)
+  finish                continue jusqu'au retour de la fonction courante (f)
+  continue              continue jusqu'au prochain arrêt (c)
+  print [stack|env|accu|all]
+                        affiche l'état de la machine (p)
+  watch slot <i>        surveille la case i de la pile, comptée depuis le fond
+  watch field <b> <i>   surveille le champ i du bloc #b
+  backtrace             pile des appels (bt)
+  list [n]              instructions autour du pc (l)
+  info                  registres, points d'arrêt et surveillances (i)
+  quit                  quitte le débogueur (q)
+une ligne vide répète la commande précédente";
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Watch {
+    //case de la pile comptée depuis le fond, elle ne bouge pas quand la pile grandit
+    Slot(usize),
+    Field { block: usize, field: usize },
+}
+
+impl Watch {
+    fn read(&self, machine: &ZAM) -> Option<Mlvalue> {
+        match self {
+            Watch::Slot(i) => machine.stack().get(*i).cloned(),
+            Watch::Field { block, field } => machine
+                .heap()
+                .get(*block)
+                .ok()
+                .and_then(|fields| fields.get(*field).cloned()),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Watch::Slot(i) => format!("case {} de la pile", i),
+            Watch::Field { block, field } => format!("champ {} du bloc #{}", field, block),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    pub breakpoints: BTreeSet<usize>,
+    //chaque surveillance garde la dernière valeur vue
+    pub watches: Vec<(Watch, Option<Mlvalue>)>,
+    //issue du programme une fois terminé
+    pub finished: Option<Result<Outcome, VmError>>,
+    last_command: String,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    //un label ou un indice d'instruction
+    pub fn add_breakpoint(&mut self, machine: &ZAM, spec: &str) -> Result<usize, String> {
+        let pc = match machine.label(spec) {
+            Some(pc) => pc,
+            None => match spec.parse::<usize>() {
+                Ok(pc) if pc < machine.code().len() => pc,
+                _ => return Err(format!("ni label ni instruction : {}", spec)),
+            },
+        };
+        self.breakpoints.insert(pc);
+        Ok(pc)
+    }
+
+    pub fn add_watch(&mut self, machine: &ZAM, watch: Watch) {
+        let value = watch.read(machine);
+        self.watches.push((watch, value));
+    }
+
+    //lit les commandes jusqu'à quit ou la fin de l'entrée
+    pub fn repl<R: BufRead, W: Write>(
+        &mut self,
+        machine: &mut ZAM,
+        input: R,
+        out: &mut W,
+    ) -> io::Result<()> {
+        self.show_location(machine, out)?;
+        write!(out, "(zam) ")?;
+        out.flush()?;
+        for line in input.lines() {
+            if !self.command(machine, &line?, out)? {
+                return Ok(());
+            }
+            write!(out, "(zam) ")?;
+            out.flush()?;
+        }
+        writeln!(out)
+    }
+
+    //exécute une commande, renvoie faux pour quitter
+    pub fn command<W: Write>(
+        &mut self,
+        machine: &mut ZAM,
+        line: &str,
+        out: &mut W,
+    ) -> io::Result<bool> {
+        let line = if line.trim().is_empty() {
+            self.last_command.clone()
+        } else {
+            self.last_command = line.to_string();
+            line.to_string()
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (*name, args),
+            None => return Ok(true),
+        };
+
+        match name {
+            "b" | "break" => match args.first() {
+                Some(spec) => match self.add_breakpoint(machine, spec) {
+                    Ok(pc) => writeln!(out, "point d'arrêt en {} ({})", pc, machine.locate(pc))?,
+                    Err(e) => writeln!(out, "{}", e)?,
+                },
+                None => writeln!(out, "break attend un label ou un pc")?,
+            },
+            "d" | "delete" => match args.first() {
+                Some(&"all") => {
+                    self.breakpoints.clear();
+                    self.watches.clear();
+                }
+                Some(spec) => match spec.parse::<usize>() {
+                    Ok(pc) if self.breakpoints.remove(&pc) => {}
+                    _ => match machine.label(spec) {
+                        Some(pc) if self.breakpoints.remove(&pc) => {}
+                        _ => writeln!(out, "aucun point d'arrêt en {}", spec)?,
+                    },
+                },
+                None => writeln!(out, "delete attend un pc, un label ou all")?,
+            },
+            "s" | "step" => {
+                let n = args.first().and_then(|n| n.parse().ok()).unwrap_or(1);
+                let mut done = 0;
+                self.resume(machine, out, |_| {
+                    done += 1;
+                    done >= n
+                })?;
+            }
+            "n" | "next" => match machine.code().get(machine.pc()) {
+                Some(Inst::Apply(n)) => {
+                    let ret = machine.pc() + 1;
+                    let sp = machine.stack().len().saturating_sub(*n as usize);
+                    self.resume(machine, out, |m| m.pc() == ret && m.stack().len() <= sp)?;
+                }
+                _ => self.resume(machine, out, |_| true)?,
+            },
+            "f" | "finish" => match machine.backtrace().get(1) {
+                Some(caller) => {
+                    let (ret, sp) = (caller.pc + 1, caller.sp);
+                    self.resume(machine, out, |m| m.pc() == ret && m.stack().len() <= sp)?;
+                }
+                None => writeln!(out, "aucun appel en cours")?,
+            },
+            "c" | "continue" => self.resume(machine, out, |_| false)?,
+            "p" | "print" => self.print(machine, args.first().copied().unwrap_or("all"), out)?,
+            "watch" => match parse_watch(args) {
+                Some(watch) => {
+                    writeln!(out, "surveillance de la {}", watch.describe())?;
+                    self.add_watch(machine, watch);
+                }
+                None => writeln!(out, "watch slot <i> ou watch field <bloc> <i>")?,
+            },
+            "bt" | "backtrace" => {
+                for (i, frame) in machine.backtrace().iter().enumerate() {
+                    let inst = machine.prog().get(frame.pc).map(|(_, inst)| inst);
+                    write!(out, "#{} {} ({})", i, frame.pc, machine.locate(frame.pc))?;
+                    if let Some(inst) = inst {
+                        write!(out, "\t{}", inst)?;
+                    }
+                    writeln!(out, "\textra args {}", frame.extra_args)?;
+                }
+            }
+            "l" | "list" => {
+                let n = args.first().and_then(|n| n.parse().ok()).unwrap_or(4);
+                let start = machine.pc().saturating_sub(n);
+                let end = (machine.pc() + n + 1).min(machine.prog().len());
+                for i in start..end {
+                    let (label, inst) = &machine.prog()[i];
+                    let marker = if i == machine.pc() { "=>" } else { "  " };
+                    let stop = if self.breakpoints.contains(&i) {
+                        "*"
+                    } else {
+                        " "
+                    };
+                    let label = label
+                        .as_ref()
+                        .map(|l| format!("{}:", l))
+                        .unwrap_or_default();
+                    writeln!(out, "{}{}{:>4} {}\t{}", marker, stop, i, label, inst)?;
+                }
+            }
+            "i" | "info" => {
+                writeln!(
+                    out,
+                    "pc {} ({}), extra args {}, trap sp {:?}, pile {}, {} instructions exécutées",
+                    machine.pc(),
+                    machine.locate(machine.pc()),
+                    machine.extra_args(),
+                    machine.trap_sp(),
+                    machine.stack().len(),
+                    machine.steps()
+                )?;
+                for pc in &self.breakpoints {
+                    writeln!(out, "point d'arrêt en {} ({})", pc, machine.locate(*pc))?;
+                }
+                for (watch, value) in &self.watches {
+                    let value = value.as_ref().map(|v| show(machine, v));
+                    writeln!(
+                        out,
+                        "surveillance de la {} : {}",
+                        watch.describe(),
+                        value.unwrap_or_else(|| "absente".to_string())
+                    )?;
+                }
+            }
+            "h" | "help" => writeln!(out, "{}", HELP)?,
+            "q" | "quit" => return Ok(false),
+            _ => writeln!(out, "commande inconnue {}, voir help", name)?,
+        }
+        Ok(true)
+    }
+
+    //exécute jusqu'à ce que stop soit vrai après une instruction, un point
+    //d'arrêt, le changement d'une valeur surveillée ou la fin du programme
+    fn resume<W: Write, F: FnMut(&ZAM) -> bool>(
+        &mut self,
+        machine: &mut ZAM,
+        out: &mut W,
+        mut stop: F,
+    ) -> io::Result<()> {
+        if let Some(result) = &self.finished {
+            return match result {
+                Ok(_) => writeln!(out, "le programme est terminé"),
+                Err(_) => writeln!(out, "la machine s'est arrêtée sur une erreur"),
+            };
+        }
+        loop {
+            match machine.step() {
+                Ok(None) => {}
+                Ok(Some(outcome)) => {
+                    match &outcome {
+                        Outcome::Value(v) => writeln!(out, "fin : {}", show(machine, v))?,
+                        Outcome::Exception(v) => {
+                            writeln!(out, "exception non rattrapée : {}", show(machine, v))?
+                        }
+                        Outcome::OutOfFuel => writeln!(out, "carburant épuisé")?,
+                        Outcome::StackOverflow => writeln!(out, "débordement de pile")?,
+                        Outcome::HeapExhausted => writeln!(out, "tas plein")?,
+                    }
+                    self.finished = Some(Ok(outcome));
+                    return Ok(());
+                }
+                Err(e) => {
+                    writeln!(out, "erreur : {}", e)?;
+                    write!(out, "{}", format_backtrace(&e.backtrace))?;
+                    self.finished = Some(Err(e));
+                    return Ok(());
+                }
+            }
+
+            let mut changed = false;
+            for (watch, last) in self.watches.iter_mut() {
+                let value = watch.read(machine);
+                if value != *last {
+                    let show_opt = |v: &Option<Mlvalue>| {
+                        v.as_ref()
+                            .map(|v| show(machine, v))
+                            .unwrap_or_else(|| "absente".to_string())
+                    };
+                    writeln!(
+                        out,
+                        "{} : {} -> {}",
+                        watch.describe(),
+                        show_opt(last),
+                        show_opt(&value)
+                    )?;
+                    *last = value;
+                    changed = true;
+                }
+            }
+            if changed || stop(machine) {
+                break;
+            }
+            if self.breakpoints.contains(&machine.pc()) {
+                writeln!(out, "point d'arrêt en {}", machine.pc())?;
+                break;
+            }
+        }
+        self.show_location(machine, out)
+    }
+
+    fn show_location<W: Write>(&self, machine: &ZAM, out: &mut W) -> io::Result<()> {
+        match machine.prog().get(machine.pc()) {
+            Some((_, inst)) => writeln!(
+                out,
+                "=> {} ({})\t{}",
+                machine.pc(),
+                machine.locate(machine.pc()),
+                inst
+            ),
+            None => writeln!(out, "=> {} hors du programme", machine.pc()),
+        }
+    }
+
+    fn print<W: Write>(&self, machine: &ZAM, what: &str, out: &mut W) -> io::Result<()> {
+        if what == "accu" || what == "all" {
+            writeln!(out, "accu : {}", show(machine, machine.accu()))?;
+        }
+        if what == "env" || what == "all" {
+            writeln!(out, "env :")?;
+            for (i, v) in machine.env().iter().enumerate() {
+                writeln!(out, "  {:>3} {}", i, show(machine, v))?;
+            }
+        }
+        if what == "stack" || what == "all" {
+            //du sommet vers le fond, avec l'indice d'ACC puis celui de watch slot
+            writeln!(out, "pile :")?;
+            for (i, v) in machine.stack().iter().enumerate().rev() {
+                let acc = machine.stack().len() - 1 - i;
+                writeln!(out, "  {:>3} [{}] {}", acc, i, show(machine, v))?;
+            }
+        }
+        if !["accu", "env", "stack", "all"].contains(&what) {
+            writeln!(out, "print stack, env, accu ou all")?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_watch(args: &[&str]) -> Option<Watch> {
+    match args {
+        ["slot", i] => i.parse().ok().map(Watch::Slot),
+        ["field", block, field] => {
+            let block = block.trim_start_matches('#').parse().ok()?;
+            let field = field.parse().ok()?;
+            Some(Watch::Field { block, field })
+        }
+        _ => None,
+    }
+}
+
+//les blocs gardent leur numéro pour pouvoir être surveillés
+fn show(machine: &ZAM, v: &Mlvalue) -> String {
+    match v {
+        Mlvalue::Entier(n) => n.to_string(),
+        Mlvalue::Block(h) => format!("#{} {}", h, machine.heap().show(v)),
+        Mlvalue::Chaine(_) | Mlvalue::Flottant(_) | Mlvalue::Flottants(_) => machine.heap().show(v),
+        Mlvalue::Fermeture(pc, env) => {
+            let env: Vec<String> = env.iter().map(|v| show(machine, v)).collect();
+            format!("fermeture {} [{}]", machine.locate(*pc), env.join(", "))
+        }
+        Mlvalue::Environement(env) => {
+            let env: Vec<String> = env.iter().map(|v| show(machine, v)).collect();
+            format!("env [{}]", env.join(", "))
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//affichage d'un programme dans le format texte lu par parser::parse_prog ;
+//indices et annotations sont écrits en commentaire après un `;`
+use crate::linker;
+use crate::machine::Inst;
+use crate::Program;
+
+use std::collections::HashSet;
+
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct DisasmOptions {
+    //indice de chaque instruction et de la cible des sauts
+    pub indices: bool,
+    //renomme chaque label Ln, n étant l'indice de l'instruction désignée
+    pub synthesize_labels: bool,
+    //arité des fermetures créées
+    pub annotate: bool,
+}
+
+impl DisasmOptions {
+    pub fn all() -> DisasmOptions {
+        DisasmOptions {
+            indices: true,
+            synthesize_labels: true,
+            annotate: true,
+        }
+    }
+}
+
+pub fn disasm(prog: &[(Option<String>, Inst)], options: &DisasmOptions) -> String {
+    let (positions, _) = linker::labels(prog);
+    let renamed;
+    let prog = if options.synthesize_labels {
+        let name = |l: &String| match positions.get(l) {
+            Some(pos) => format!("L{}", pos),
+            None => l.clone(),
+        };
+        renamed = prog
+            .iter()
+            .map(|(l, inst)| (l.as_ref().map(name), inst.map_labels(name)))
+            .collect::<Program>();
+        &renamed
+    } else {
+        prog
+    };
+
+    let (positions, _) = linker::labels(prog);
+    let mut text = String::new();
+    for (i, (label, inst)) in prog.iter().enumerate() {
+        let mut notes = Vec::new();
+        if options.indices {
+            notes.push(i.to_string());
+            let targets: Vec<String> = inst
+                .targets()
+                .iter()
+                .filter_map(|l| positions.get(*l))
+                .map(|pos| pos.to_string())
+                .collect();
+            if !targets.is_empty() {
+                notes.push(format!("vers {}", targets.join(", ")));
+            }
+        }
+        if options.annotate {
+            if let Inst::Closure(l, n) | Inst::ClosureRec(l, n) = inst {
+                if let Some(pos) = positions.get(l) {
+                    notes.push(format!("arité {}, {} libres", arity(prog, *pos), n));
+                }
+            }
+        }
+
+        if let Some(l) = label {
+            text.push_str(l);
+            text.push(':');
+        }
+        text.push('\t');
+        if notes.is_empty() {
+            text.push_str(&inst.to_string());
+        } else {
+            text.push_str(&format!("{:<20}; {}", inst.to_string(), notes.join(", ")));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+//programme lié : chaque cible reçoit le label Ln
+pub fn disasm_linked(code: &[Inst<usize>], options: &DisasmOptions) -> String {
+    let targets: HashSet<usize> = code
+        .iter()
+        .flat_map(|inst| inst.targets())
+        .copied()
+        .collect();
+    let prog: Program = code
+        .iter()
+        .enumerate()
+        .map(|(i, inst)| {
+            let label = if targets.contains(&i) {
+                Some(format!("L{}", i))
+            } else {
+                None
+            };
+            (label, inst.map_labels(|t| format!("L{}", t)))
+        })
+        .collect();
+    disasm(&prog, options)
+}
+
+//une fonction n-aire commence par GRAB n-1
+fn arity(prog: &[(Option<String>, Inst)], pos: usize) -> i64 {
+    match prog.get(pos) {
+        Some((_, Inst::Grab(n))) => n + 1,
+        _ => 1,
+    }
+}
@@ -0,0 +1,118 @@
+use crate::machine::Inst;
+use crate::mlvalue::Mlvalue;
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ErrorKind {
+    //la valeur n'a pas le type attendu par l'instruction
+    TypeMismatch {
+        expected: &'static str,
+        found: Mlvalue,
+    },
+    StackUnderflow,
+    UnknownLabel(String),
+    BadFieldIndex {
+        index: i64,
+        size: usize,
+    },
+    DivisionByZero,
+    UnknownPrimitive(String),
+    //argument d'instruction invalide (nombre négatif, n > m ...)
+    BadOperand(String),
+    PcOutOfRange,
+    //bloc déjà libéré par le GC
+    DanglingBlock(usize),
+    //erreur signalée par une fonction de l'hôte
+    Host(String),
+    //lecture ou écriture du terminal impossible en mode pas à pas
+    Io(String),
+    //exception ML levée par une primitive, rattrapable par le programme
+    Raise(Mlvalue),
+    //nombre maximal d'instructions exécutées atteint
+    StepLimit(u64),
+    //objet trop grand pour la limite du tas ou pour la mémoire de l'hôte
+    HeapExhausted,
+}
+
+//pc et label+décalage de chaque activation, la plus récente d'abord
+pub type Backtrace = Vec<(usize, String)>;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct VmError {
+    pub pc: usize,
+    pub inst: Option<Inst>,
+    pub kind: ErrorKind,
+    //activations au moment de l'erreur, vide si la machine ne l'a pas fournie
+    pub backtrace: Backtrace,
+}
+
+impl VmError {
+    pub fn new(pc: usize, inst: Option<Inst>, kind: ErrorKind) -> VmError {
+        VmError {
+            pc,
+            inst,
+            kind,
+            backtrace: Vec::new(),
+        }
+    }
+
+    pub fn with_backtrace(mut self, backtrace: Backtrace) -> VmError {
+        self.backtrace = backtrace;
+        self
+    }
+}
+
+//une ligne par activation, sans le label quand aucun ne précède le pc
+pub fn format_backtrace(backtrace: &[(usize, String)]) -> String {
+    let mut text = String::new();
+    for (i, (pc, location)) in backtrace.iter().enumerate() {
+        if *location == pc.to_string() {
+            text.push_str(&format!("  #{} pc {}\n", i, pc));
+        } else {
+            text.push_str(&format!("  #{} pc {} ({})\n", i, pc, location));
+        }
+    }
+    text
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::TypeMismatch { expected, found } => {
+                write!(
+                    f,
+                    "type incorrect : {} attendu, {:?} trouvé",
+                    expected, found
+                )
+            }
+            ErrorKind::StackUnderflow => write!(f, "pile vide"),
+            ErrorKind::UnknownLabel(l) => write!(f, "label inconnu {}", l),
+            ErrorKind::BadFieldIndex { index, size } => {
+                write!(f, "indice {} hors du bloc de taille {}", index, size)
+            }
+            ErrorKind::DivisionByZero => write!(f, "division par zéro"),
+            ErrorKind::UnknownPrimitive(p) => write!(f, "primitive inconnue {}", p),
+            ErrorKind::BadOperand(msg) => write!(f, "argument invalide : {}", msg),
+            ErrorKind::PcOutOfRange => write!(f, "pc hors du programme"),
+            ErrorKind::DanglingBlock(h) => write!(f, "bloc #{} déjà libéré", h),
+            ErrorKind::Host(msg) => write!(f, "{}", msg),
+            ErrorKind::Io(msg) => write!(f, "entrée-sortie : {}", msg),
+            ErrorKind::Raise(exn) => write!(f, "exception {:?} levée par une primitive", exn),
+            ErrorKind::StepLimit(n) => write!(f, "limite de {} instructions atteinte", n),
+            ErrorKind::HeapExhausted => write!(f, "tas plein"),
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.inst {
+            Some(inst) => write!(f, "{} (pc {} : {:?})", self.kind, self.pc, inst),
+            None => write!(f, "{} (pc {})", self.kind, self.pc),
+        }
+    }
+}
+
+impl Error for VmError {}
@@ -0,0 +1,329 @@
+use crate::error::ErrorKind;
+use crate::mlvalue::{self, Mlvalue};
+
+use std::fmt;
+
+//nombre de blocs vivants avant la première collection
+const INITIAL_THRESHOLD: usize = 256;
+//mots vivants avant la première collection, pour les grosses chaînes
+const INITIAL_WORDS_THRESHOLD: usize = 1 << 16;
+
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct GcStats {
+    pub collections: usize,
+    pub allocated: usize,
+    pub freed: usize,
+    pub live: usize,
+    pub peak: usize,
+    //taille des objets vivants en mots de 8 octets, en-têtes compris
+    pub words: usize,
+    pub peak_words: usize,
+}
+
+impl fmt::Display for GcStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "collections {}, blocs alloués {}, libérés {}, vivants {}, maximum {}, \
+             mots vivants {}, maximum {}",
+            self.collections,
+            self.allocated,
+            self.freed,
+            self.live,
+            self.peak,
+            self.words,
+            self.peak_words
+        )
+    }
+}
+
+//contenu d'une case du tas
+enum Object {
+    //étiquette et champs
+    Block(u8, Vec<Mlvalue>),
+    //chaîne d'octets modifiable
+    Bytes(Vec<u8>),
+    Float(f64),
+    //tableau de flottants non mis en boîte
+    Floats(Vec<f64>),
+}
+
+impl Object {
+    fn words(&self) -> usize {
+        match self {
+            Object::Block(_, fields) => Heap::block_words(fields.len()),
+            Object::Bytes(bytes) => Heap::string_words(bytes.len()),
+            Object::Float(_) => 2,
+            Object::Floats(floats) => Heap::floats_words(floats.len()),
+        }
+    }
+}
+
+//tas de blocs et de chaînes désignés par leur indice, ramassé par
+//marquage-balayage
+pub struct Heap {
+    blocks: Vec<Option<Object>>,
+    free: Vec<usize>,
+    threshold: usize,
+    words_threshold: usize,
+    //taille maximale des objets vivants en mots, voir reserve
+    limit: Option<usize>,
+    //mots encore vivants après le dernier ramassage
+    collected_words: usize,
+    stats: GcStats,
+}
+
+impl Default for Heap {
+    fn default() -> Heap {
+        Heap::new()
+    }
+}
+
+impl Heap {
+    pub fn new() -> Heap {
+        Heap {
+            blocks: Vec::new(),
+            free: Vec::new(),
+            threshold: INITIAL_THRESHOLD,
+            words_threshold: INITIAL_WORDS_THRESHOLD,
+            limit: None,
+            collected_words: 0,
+            stats: GcStats::default(),
+        }
+    }
+
+    pub fn alloc(&mut self, fields: Vec<Mlvalue>) -> Mlvalue {
+        self.alloc_tagged(0, fields)
+    }
+
+    pub fn alloc_tagged(&mut self, tag: u8, fields: Vec<Mlvalue>) -> Mlvalue {
+        Mlvalue::Block(self.store(Object::Block(tag, fields)))
+    }
+
+    pub fn alloc_string(&mut self, bytes: Vec<u8>) -> Mlvalue {
+        Mlvalue::Chaine(self.store(Object::Bytes(bytes)))
+    }
+
+    pub fn alloc_float(&mut self, x: f64) -> Mlvalue {
+        Mlvalue::Flottant(self.store(Object::Float(x)))
+    }
+
+    pub fn alloc_floats(&mut self, floats: Vec<f64>) -> Mlvalue {
+        Mlvalue::Flottants(self.store(Object::Floats(floats)))
+    }
+
+    //tailles en mots comme dans le tas d'OCaml : un en-tête puis les champs,
+    //les octets d'une chaîne suivis d'au moins un octet de remplissage
+    pub fn block_words(fields: usize) -> usize {
+        1 + fields
+    }
+
+    pub fn string_words(len: usize) -> usize {
+        1 + len / 8 + 1
+    }
+
+    pub fn floats_words(len: usize) -> usize {
+        1 + len
+    }
+
+    pub fn set_limit(&mut self, max: Option<usize>) {
+        self.limit = max;
+        self.words_threshold = self.words_threshold.min(self.next_words_threshold());
+    }
+
+    //vrai si le dernier ramassage a laissé plus de mots vivants que la limite
+    pub fn over_limit(&self) -> bool {
+        self.limit.is_some_and(|max| self.collected_words > max)
+    }
+
+    //avec une limite, le ramassage suivant a lieu au plus tard quand la limite
+    //est franchie une fois de plus, ce qui garde un coût amorti constant
+    fn next_words_threshold(&self) -> usize {
+        let next = INITIAL_WORDS_THRESHOLD.max(2 * self.stats.words);
+        match self.limit {
+            Some(max) => next.min(self.stats.words.saturating_add(max)),
+            None => next,
+        }
+    }
+
+    //à appeler avant de construire un objet dont le programme choisit la
+    //taille : échoue si les objets vivants dépasseraient la limite
+    pub fn reserve(&self, words: usize) -> Result<(), ErrorKind> {
+        match self.limit {
+            Some(max) if self.stats.words.saturating_add(words) > max => {
+                Err(ErrorKind::HeapExhausted)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn store(&mut self, object: Object) -> usize {
+        self.stats.allocated += 1;
+        self.stats.live += 1;
+        self.stats.peak = self.stats.peak.max(self.stats.live);
+        self.stats.words += object.words();
+        self.stats.peak_words = self.stats.peak_words.max(self.stats.words);
+        match self.free.pop() {
+            Some(h) => {
+                self.blocks[h] = Some(object);
+                h
+            }
+            None => {
+                self.blocks.push(Some(object));
+                self.blocks.len() - 1
+            }
+        }
+    }
+
+    pub fn get(&self, h: usize) -> Result<&Vec<Mlvalue>, ErrorKind> {
+        match self.blocks.get(h) {
+            Some(Some(Object::Block(_, fields))) => Ok(fields),
+            _ => Err(ErrorKind::DanglingBlock(h)),
+        }
+    }
+
+    pub fn get_mut(&mut self, h: usize) -> Result<&mut Vec<Mlvalue>, ErrorKind> {
+        match self.blocks.get_mut(h) {
+            Some(Some(Object::Block(_, fields))) => Ok(fields),
+            _ => Err(ErrorKind::DanglingBlock(h)),
+        }
+    }
+
+    pub fn tag(&self, h: usize) -> Result<u8, ErrorKind> {
+        match self.blocks.get(h) {
+            Some(Some(Object::Block(tag, _))) => Ok(*tag),
+            _ => Err(ErrorKind::DanglingBlock(h)),
+        }
+    }
+
+    pub fn get_string(&self, h: usize) -> Result<&Vec<u8>, ErrorKind> {
+        match self.blocks.get(h) {
+            Some(Some(Object::Bytes(bytes))) => Ok(bytes),
+            _ => Err(ErrorKind::DanglingBlock(h)),
+        }
+    }
+
+    pub fn get_string_mut(&mut self, h: usize) -> Result<&mut Vec<u8>, ErrorKind> {
+        match self.blocks.get_mut(h) {
+            Some(Some(Object::Bytes(bytes))) => Ok(bytes),
+            _ => Err(ErrorKind::DanglingBlock(h)),
+        }
+    }
+
+    pub fn get_float(&self, h: usize) -> Result<f64, ErrorKind> {
+        match self.blocks.get(h) {
+            Some(Some(Object::Float(x))) => Ok(*x),
+            _ => Err(ErrorKind::DanglingBlock(h)),
+        }
+    }
+
+    pub fn get_floats(&self, h: usize) -> Result<&Vec<f64>, ErrorKind> {
+        match self.blocks.get(h) {
+            Some(Some(Object::Floats(floats))) => Ok(floats),
+            _ => Err(ErrorKind::DanglingBlock(h)),
+        }
+    }
+
+    pub fn get_floats_mut(&mut self, h: usize) -> Result<&mut Vec<f64>, ErrorKind> {
+        match self.blocks.get_mut(h) {
+            Some(Some(Object::Floats(floats))) => Ok(floats),
+            _ => Err(ErrorKind::DanglingBlock(h)),
+        }
+    }
+
+    //nombre de cases du tas, blocs libres compris
+    pub fn size(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn stats(&self) -> &GcStats {
+        &self.stats
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.stats.live >= self.threshold || self.stats.words >= self.words_threshold
+    }
+
+    pub fn collect<'a, I: IntoIterator<Item = &'a Mlvalue>>(&mut self, roots: I) {
+        let mut marks = vec![false; self.blocks.len()];
+        let mut todo: Vec<&Mlvalue> = roots.into_iter().collect();
+
+        while let Some(v) = todo.pop() {
+            match v {
+                Mlvalue::Block(h) => {
+                    if !marks[*h] {
+                        marks[*h] = true;
+                        if let Some(Some(Object::Block(_, fields))) = self.blocks.get(*h) {
+                            todo.extend(fields.iter());
+                        }
+                    }
+                }
+                Mlvalue::Chaine(h) | Mlvalue::Flottant(h) | Mlvalue::Flottants(h) => {
+                    marks[*h] = true
+                }
+                Mlvalue::Environement(env) | Mlvalue::Fermeture(_, env) => todo.extend(env.iter()),
+                Mlvalue::Entier(_) => {}
+            }
+        }
+
+        for (h, marked) in marks.iter().enumerate() {
+            if !marked {
+                if let Some(object) = self.blocks[h].take() {
+                    self.free.push(h);
+                    self.stats.freed += 1;
+                    self.stats.live -= 1;
+                    self.stats.words -= object.words();
+                }
+            }
+        }
+        self.stats.collections += 1;
+        self.threshold = INITIAL_THRESHOLD.max(2 * self.stats.live);
+        self.words_threshold = self.next_words_threshold();
+        self.collected_words = self.stats.words;
+    }
+
+    //affichage d'une valeur en suivant les blocs, à profondeur bornée
+    pub fn show(&self, v: &Mlvalue) -> String {
+        self.show_depth(v, 16)
+    }
+
+    fn show_depth(&self, v: &Mlvalue, depth: usize) -> String {
+        match v {
+            Mlvalue::Block(h) if depth == 0 => format!("Block(#{})", h),
+            Mlvalue::Block(h) => match self.get(*h) {
+                Ok(fields) => {
+                    let fields: Vec<String> = fields
+                        .iter()
+                        .map(|f| self.show_depth(f, depth - 1))
+                        .collect();
+                    //l'étiquette 0 des n-uplets et des listes n'est pas affichée
+                    match self.tag(*h) {
+                        Ok(0) => format!("Block([{}])", fields.join(", ")),
+                        Ok(tag) => format!("Block({}: [{}])", tag, fields.join(", ")),
+                        Err(_) => unreachable!(),
+                    }
+                }
+                Err(_) => format!("Block(#{} libéré)", h),
+            },
+            Mlvalue::Chaine(h) => match self.get_string(*h) {
+                Ok(bytes) => format!("Chaine({:?})", String::from_utf8_lossy(bytes)),
+                Err(_) => format!("Chaine(#{} libérée)", h),
+            },
+            Mlvalue::Flottant(h) => match self.get_float(*h) {
+                Ok(x) => format!("Flottant({})", mlvalue::string_of_float(x)),
+                Err(_) => format!("Flottant(#{} libéré)", h),
+            },
+            Mlvalue::Flottants(h) => match self.get_floats(*h) {
+                Ok(floats) => {
+                    let floats: Vec<String> = floats
+                        .iter()
+                        .map(|x| mlvalue::string_of_float(*x))
+                        .collect();
+                    format!("Flottants([{}])", floats.join(", "))
+                }
+                Err(_) => format!("Flottants(#{} libéré)", h),
+            },
+            _ => format!("{:?}", v),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//interprète Mini-ZAM : chargement de programmes (bytecode texte ou
+//source mini-ML), configuration et exécution d'une machine
+pub mod bytecode;
+pub mod compiler;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod heap;
+pub mod linker;
+pub mod machine;
+pub mod mlvalue;
+pub mod optimizer;
+pub mod parser;
+pub mod primitives;
+pub mod profile;
+pub mod trace;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+pub use crate::bytecode::{read_bytecode, write_bytecode, BytecodeError};
+pub use crate::compiler::CompileError;
+pub use crate::debugger::{Debugger, Watch};
+pub use crate::disasm::{disasm, disasm_linked, DisasmOptions};
+pub use crate::error::{ErrorKind, VmError};
+pub use crate::heap::Heap;
+pub use crate::linker::LinkError;
+pub use crate::machine::{Callback, Frame, Inst, Outcome, ZAM};
+pub use crate::mlvalue::Mlvalue;
+pub use crate::optimizer::{optimize, Passes};
+pub use crate::parser::ParseError;
+pub use crate::primitives::{Context, IntMode, Native, Primitive, Primitives};
+pub use crate::profile::{Profile, ProfileFormat};
+pub use crate::trace::{TraceCallback, TraceLevel, TraceSink, Tracer};
+
+pub type Program = Vec<(Option<String>, Inst)>;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(Vec<ParseError>),
+    Compile(CompileError),
+    //labels non définis ou définis deux fois
+    Link(Vec<LinkError>),
+    Bytecode(BytecodeError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::Parse(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", errors.join("\n"))
+            }
+            LoadError::Compile(e) => write!(f, "{}", e),
+            LoadError::Link(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", errors.join("\n"))
+            }
+            LoadError::Bytecode(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> LoadError {
+        LoadError::Io(e)
+    }
+}
+
+//bytecode au format texte ; name ne sert qu'aux messages d'erreur
+pub fn load_str(source: &str, name: &str) -> Result<Program, LoadError> {
+    load_reader(source.as_bytes(), name)
+}
+
+pub fn load_reader<R: BufRead>(reader: R, name: &str) -> Result<Program, LoadError> {
+    let prog = parser::parse_prog(reader, name).map_err(LoadError::Parse)?;
+    linker::check(&prog).map_err(LoadError::Link)?;
+    Ok(prog)
+}
+
+//source mini-ML
+pub fn compile_str(source: &str) -> Result<Program, LoadError> {
+    let prog = compiler::compile(source).map_err(LoadError::Compile)?;
+    linker::check(&prog).map_err(LoadError::Link)?;
+    Ok(prog)
+}
+
+//un fichier .ml est compilé, un fichier commençant par bytecode::MAGIC est
+//lu comme du bytecode binaire, tout autre fichier comme du bytecode texte
+pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Program, LoadError> {
+    let path = path.as_ref();
+    if path.extension().and_then(|e| e.to_str()) == Some("ml") {
+        let source = std::fs::read_to_string(path)?;
+        return compile_str(&source);
+    }
+    let mut fichier = BufReader::new(File::open(path)?);
+    if bytecode::is_bytecode(fichier.fill_buf()?) {
+        read_bytecode(&mut fichier).map_err(LoadError::Bytecode)
+    } else {
+        load_reader(fichier, &path.display().to_string())
+    }
+}
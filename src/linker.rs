@@ -0,0 +1,144 @@
+//résolution des labels en indices d'instruction, faite une fois au chargement
+use crate::machine::Inst;
+use crate::primitives::Primitives;
+
+use std::collections::HashMap;
+use std::fmt;
+
+//cible d'un label non défini dans un programme lié par resolve
+pub const UNRESOLVED: usize = usize::MAX;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LinkError {
+    Undefined {
+        label: String,
+        pc: usize,
+    },
+    Duplicate {
+        label: String,
+        first: usize,
+        second: usize,
+    },
+    UnknownPrimitive {
+        name: String,
+        pc: usize,
+    },
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkError::Undefined { label, pc } => {
+                write!(f, "instruction {} : label {} non défini", pc, label)
+            }
+            LinkError::Duplicate {
+                label,
+                first,
+                second,
+            } => write!(
+                f,
+                "label {} défini deux fois (instructions {} et {})",
+                label, first, second
+            ),
+            LinkError::UnknownPrimitive { name, pc } => {
+                write!(f, "instruction {} : primitive {} inconnue", pc, name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+//indice de chaque label, la première définition l'emporte
+pub fn labels(prog: &[(Option<String>, Inst)]) -> (HashMap<String, usize>, Vec<LinkError>) {
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    let mut errors = Vec::new();
+    for (i, (label, _)) in prog.iter().enumerate() {
+        if let Some(label) = label {
+            match labels.get(label) {
+                Some(&first) => errors.push(LinkError::Duplicate {
+                    label: label.clone(),
+                    first,
+                    second: i,
+                }),
+                None => {
+                    labels.insert(label.clone(), i);
+                }
+            }
+        }
+    }
+    (labels, errors)
+}
+
+//les labels non définis deviennent UNRESOLVED et sont ajoutés à errors
+pub fn resolve(
+    prog: &[(Option<String>, Inst)],
+    labels: &HashMap<String, usize>,
+    errors: &mut Vec<LinkError>,
+) -> Vec<Inst<usize>> {
+    prog.iter()
+        .enumerate()
+        .map(|(pc, (_, inst))| {
+            inst.map_labels(|l| match labels.get(l) {
+                Some(&target) => target,
+                None => {
+                    errors.push(LinkError::Undefined {
+                        label: l.clone(),
+                        pc,
+                    });
+                    UNRESOLVED
+                }
+            })
+        })
+        .collect()
+}
+
+pub fn link(prog: &[(Option<String>, Inst)]) -> Result<Vec<Inst<usize>>, Vec<LinkError>> {
+    let (labels, mut errors) = labels(prog);
+    let code = resolve(prog, &labels, &mut errors);
+    if errors.is_empty() {
+        Ok(code)
+    } else {
+        Err(errors)
+    }
+}
+
+//indice de la primitive de chaque PRIM, UNRESOLVED pour les autres
+//instructions ; les primitives inconnues sont ajoutées à errors
+pub fn primitives(
+    prog: &[(Option<String>, Inst)],
+    primitives: &Primitives,
+    errors: &mut Vec<LinkError>,
+) -> Vec<usize> {
+    prog.iter()
+        .enumerate()
+        .map(|(pc, (_, inst))| match inst {
+            Inst::Prim(name) => primitives.id(name).unwrap_or_else(|| {
+                errors.push(LinkError::UnknownPrimitive {
+                    name: name.clone(),
+                    pc,
+                });
+                UNRESOLVED
+            }),
+            _ => UNRESOLVED,
+        })
+        .collect()
+}
+
+//vérifie un programme sans garder le code lié
+pub fn check(prog: &[(Option<String>, Inst)]) -> Result<(), Vec<LinkError>> {
+    link(prog).map(|_| ())
+}
+
+pub fn check_primitives(
+    prog: &[(Option<String>, Inst)],
+    table: &Primitives,
+) -> Result<(), Vec<LinkError>> {
+    let mut errors = Vec::new();
+    primitives(prog, table, &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
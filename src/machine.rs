@@ -1,25 +1,35 @@
-use crate::mlvalue::Mlvalue;
+use crate::error::{self, Backtrace, ErrorKind, VmError};
+use crate::heap::Heap;
+use crate::linker::{self, LinkError, UNRESOLVED};
+use crate::mlvalue::{self, Mlvalue};
+use crate::parser;
+use crate::primitives::{self, Context, IntMode, Native, Primitive, Primitives};
+use crate::profile::Profile;
+use crate::trace::{TraceLevel, TraceSink, Tracer};
 
 use std::collections::HashMap;
+use std::fmt;
 use std::io;
 use std::io::prelude::*;
 
+//les labels sont des noms dans le programme chargé et des indices
+//d'instruction une fois le programme lié
 #[derive(Debug, PartialEq, Clone)]
-pub enum Inst {
+pub enum Inst<L = String> {
     Const(i64),
     Prim(String),
-    Branch(String),
-    BranchIfNot(String),
+    Branch(L),
+    BranchIfNot(L),
     Push,
     Pop,
     Acc(u64),
     Envacc(u64),
-    Closure(String, i64),
+    Closure(L, i64),
     Apply(i64),
     Return(i64),
     Stop,
     //fermeture recurcive
-    ClosureRec(String, i64),
+    ClosureRec(L, i64),
     OffSetClosure,
     //fonction n aire
     Grab(i64),
@@ -27,203 +37,649 @@ pub enum Inst {
     //Appterm
     AppTerm(i64, i64),
     //blocs mutables
-    Makeblock(i64),
+    //taille, étiquette
+    Makeblock(i64, i64),
     Getfield(i64),
     Veclength,
     Getvectitem,
+    //étiquette du bloc de l'accu
+    GetTag,
     Setfield(i64),
     SetVectitem,
     Assign(i64),
+    //exceptions
+    PushTrap(L),
+    PopTrap,
+    Raise,
+    //chaîne constante, copiée dans le tas à chaque exécution
+    ConstString(String),
+    //flottant constant, mis en boîte à chaque exécution
+    ConstFloat(f64),
+    //saut selon la valeur d'un entier de l'accu (constructeur constant) ou
+    //selon l'étiquette d'un bloc ; les tables sont en boîte pour que Inst,
+    //copiée dans chaque VmError, reste petite
+    Switch(Box<[L]>, Box<[L]>),
 }
 
-pub struct ZAM {
-    pub prog: Vec<(Option<String>, Inst)>,
-    pub stack: Vec<Mlvalue>,
-    pub env: Vec<Mlvalue>,
+impl<L> Inst<L> {
+    //labels auxquels l'instruction fait référence
+    pub fn targets(&self) -> Vec<&L> {
+        match self {
+            Inst::Branch(l)
+            | Inst::BranchIfNot(l)
+            | Inst::Closure(l, _)
+            | Inst::ClosureRec(l, _)
+            | Inst::PushTrap(l) => vec![l],
+            Inst::Switch(consts, tags) => consts.iter().chain(tags).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn targets_mut(&mut self) -> Vec<&mut L> {
+        match self {
+            Inst::Branch(l)
+            | Inst::BranchIfNot(l)
+            | Inst::Closure(l, _)
+            | Inst::ClosureRec(l, _)
+            | Inst::PushTrap(l) => vec![l],
+            Inst::Switch(consts, tags) => consts.iter_mut().chain(tags).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    //l'instruction suivante n'est jamais atteinte en séquence
+    pub fn is_terminator(&self) -> bool {
+        matches!(
+            self,
+            Inst::Branch(_)
+                | Inst::Stop
+                | Inst::Return(_)
+                | Inst::AppTerm(_, _)
+                | Inst::Raise
+                | Inst::Switch(_, _)
+        )
+    }
+
+    pub fn map_labels<M, F: FnMut(&L) -> M>(&self, mut f: F) -> Inst<M> {
+        match self {
+            Inst::Const(n) => Inst::Const(*n),
+            Inst::Prim(op) => Inst::Prim(op.clone()),
+            Inst::Branch(l) => Inst::Branch(f(l)),
+            Inst::BranchIfNot(l) => Inst::BranchIfNot(f(l)),
+            Inst::Push => Inst::Push,
+            Inst::Pop => Inst::Pop,
+            Inst::Acc(n) => Inst::Acc(*n),
+            Inst::Envacc(n) => Inst::Envacc(*n),
+            Inst::Closure(l, n) => Inst::Closure(f(l), *n),
+            Inst::Apply(n) => Inst::Apply(*n),
+            Inst::Return(n) => Inst::Return(*n),
+            Inst::Stop => Inst::Stop,
+            Inst::ClosureRec(l, n) => Inst::ClosureRec(f(l), *n),
+            Inst::OffSetClosure => Inst::OffSetClosure,
+            Inst::Grab(n) => Inst::Grab(*n),
+            Inst::Restart => Inst::Restart,
+            Inst::AppTerm(n, m) => Inst::AppTerm(*n, *m),
+            Inst::Makeblock(n, tag) => Inst::Makeblock(*n, *tag),
+            Inst::Getfield(n) => Inst::Getfield(*n),
+            Inst::Veclength => Inst::Veclength,
+            Inst::Getvectitem => Inst::Getvectitem,
+            Inst::GetTag => Inst::GetTag,
+            Inst::Setfield(n) => Inst::Setfield(*n),
+            Inst::SetVectitem => Inst::SetVectitem,
+            Inst::Assign(n) => Inst::Assign(*n),
+            Inst::PushTrap(l) => Inst::PushTrap(f(l)),
+            Inst::PopTrap => Inst::PopTrap,
+            Inst::Raise => Inst::Raise,
+            Inst::ConstString(s) => Inst::ConstString(s.clone()),
+            Inst::ConstFloat(x) => Inst::ConstFloat(*x),
+            Inst::Switch(consts, tags) => Inst::Switch(
+                consts.iter().map(&mut f).collect(),
+                tags.iter().map(&mut f).collect(),
+            ),
+        }
+    }
+}
+
+impl<L> Inst<L> {
+    //nom de l'instruction dans le format texte
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Inst::Const(_) => "CONST",
+            Inst::Prim(_) => "PRIM",
+            Inst::Branch(_) => "BRANCH",
+            Inst::BranchIfNot(_) => "BRANCHIFNOT",
+            Inst::Push => "PUSH",
+            Inst::Pop => "POP",
+            Inst::Acc(_) => "ACC",
+            Inst::Envacc(_) => "ENVACC",
+            Inst::Closure(_, _) => "CLOSURE",
+            Inst::Apply(_) => "APPLY",
+            Inst::Return(_) => "RETURN",
+            Inst::Stop => "STOP",
+            Inst::ClosureRec(_, _) => "CLOSUREREC",
+            Inst::OffSetClosure => "OFFSETCLOSURE",
+            Inst::Grab(_) => "GRAB",
+            Inst::Restart => "RESTART",
+            Inst::AppTerm(_, _) => "APPTERM",
+            Inst::Makeblock(_, _) => "MAKEBLOCK",
+            Inst::Getfield(_) => "GETFIELD",
+            Inst::Veclength => "VECTLENGTH",
+            Inst::Getvectitem => "GETVECTITEM",
+            Inst::GetTag => "GETTAG",
+            Inst::Setfield(_) => "SETFIELD",
+            Inst::SetVectitem => "SETVECTITEM",
+            Inst::Assign(_) => "ASSIGN",
+            Inst::PushTrap(_) => "PUSHTRAP",
+            Inst::PopTrap => "POPTRAP",
+            Inst::Raise => "RAISE",
+            Inst::ConstString(_) => "CONSTSTRING",
+            Inst::ConstFloat(_) => "CONSTFLOAT",
+            Inst::Switch(_, _) => "SWITCH",
+        }
+    }
+}
+
+//syntaxe du format texte lu par parser::parse_prog
+impl<L: fmt::Display> fmt::Display for Inst<L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic())?;
+        match self {
+            Inst::Const(n)
+            | Inst::Apply(n)
+            | Inst::Return(n)
+            | Inst::Grab(n)
+            | Inst::Getfield(n)
+            | Inst::Setfield(n)
+            | Inst::Assign(n) => write!(f, " {}", n),
+            Inst::Acc(n) | Inst::Envacc(n) => write!(f, " {}", n),
+            Inst::Prim(op) => write!(f, " {}", op),
+            Inst::Branch(l) | Inst::BranchIfNot(l) | Inst::PushTrap(l) => write!(f, " {}", l),
+            Inst::Closure(l, n) | Inst::ClosureRec(l, n) => write!(f, " {},{}", l, n),
+            Inst::AppTerm(n, m) => write!(f, " {},{}", n, m),
+            //l'étiquette 0 est facultative
+            Inst::Makeblock(n, 0) => write!(f, " {}", n),
+            Inst::Makeblock(n, tag) => write!(f, " {},{}", n, tag),
+            Inst::ConstString(s) => write!(f, " {}", parser::quote(s)),
+            //écriture la plus courte relue à l'identique
+            Inst::ConstFloat(x) => write!(f, " {:?}", x),
+            Inst::Switch(consts, tags) => {
+                let list = |labels: &[L]| {
+                    let labels: Vec<String> = labels.iter().map(|l| l.to_string()).collect();
+                    format!("[{}]", labels.join(","))
+                };
+                write!(f, " {} {}", list(consts), list(tags))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Outcome {
+    //valeur de l'accu au STOP
+    Value(Mlvalue),
+    //exception remontée sans rattrapeur
+    Exception(Mlvalue),
+    //le carburant donné par set_fuel est épuisé avant l'instruction du pc
+    //courant, resume reprend l'exécution
+    OutOfFuel,
+    //la pile dépasse le nombre de valeurs donné par set_max_stack
+    StackOverflow,
+    //les objets vivants dépassent la taille en mots donnée par set_max_heap
+    //même après une collection
+    HeapExhausted,
+}
+
+//activation retrouvée dans la pile : pc de l'instruction en cours, ou de
+//l'APPLY qui attend le retour de l'appel pour les appelants
+#[derive(Debug, PartialEq, Clone)]
+pub struct Frame {
     pub pc: usize,
-    pub accu: Mlvalue,
+    //taille de la pile au retour dans cette activation
+    pub sp: usize,
+    pub env: Vec<Mlvalue>,
     pub extra_args: usize,
-    pub labelsMap: HashMap<String, usize>,
-    pub options: Vec<String>,
+}
+
+//primitive de l'hôte à un argument, voir register_callback ;
+//elle reçoit l'accu et renvoie le nouvel accu
+pub type Callback = Box<dyn FnMut(&Mlvalue, &mut Heap) -> Result<Mlvalue, ErrorKind>>;
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct ZAM {
+    prog: Vec<(Option<String>, Inst)>,
+    //programme lié, c'est lui qui est exécuté
+    code: Vec<Inst<usize>>,
+    stack: Vec<Mlvalue>,
+    env: Vec<Mlvalue>,
+    pc: usize,
+    accu: Mlvalue,
+    heap: Heap,
+    extra_args: usize,
+    //position dans la pile du rattrapeur courant
+    trap_sp: Option<usize>,
+    labels_map: HashMap<String, usize>,
+    options: Vec<String>,
+    //sortie du programme (primitive print)
+    output: Box<dyn Write>,
+    primitives: Primitives,
+    //indice dans primitives de chaque PRIM du programme, UNRESOLVED sinon
+    prim_ids: Vec<usize>,
+    //arithmétique des primitives entières
+    ints: IntMode,
+    //diagnostics de l'interprète
+    tracer: Tracer,
+    //nombre d'instructions exécutées par run et limite éventuelle
+    steps: u64,
+    max_steps: Option<u64>,
+    //instructions restant à exécuter avant Outcome::OutOfFuel
+    fuel: Option<u64>,
+    //nombre maximal de valeurs dans la pile et de blocs vivants
+    max_stack: Option<usize>,
+    max_heap: Option<usize>,
+    //compteurs du profileur, voir enable_profiling
+    profile: Option<Profile>,
 }
 
 impl ZAM {
-    pub fn new(prog: &Vec<(Option<String>, Inst)>) -> ZAM {
-        let mut labels: HashMap<String, usize> = HashMap::new();
+    pub fn new(prog: &[(Option<String>, Inst)]) -> ZAM {
+        //les erreurs de liaison sont signalées au chargement (voir linker::link),
+        //un label non défini ou une primitive inconnue ne provoque ici une
+        //erreur que s'il est atteint
+        ZAM::load(prog, Primitives::new()).0
+    }
 
-        for (i, value) in prog.into_iter().enumerate() {
-            let val = value.0.clone();
-            if !val.is_none() {
-                let label: String = val.unwrap();
-                labels.insert(label, i);
-            }
+    //machine dont les PRIM sont résolus dans la table primitives de l'hôte,
+    //échoue si un label n'est pas défini ou une primitive n'existe pas
+    pub fn with_primitives(
+        prog: &[(Option<String>, Inst)],
+        primitives: Primitives,
+    ) -> Result<ZAM, Vec<LinkError>> {
+        match ZAM::load(prog, primitives) {
+            (machine, errors) if errors.is_empty() => Ok(machine),
+            (_, errors) => Err(errors),
         }
+    }
+
+    fn load(prog: &[(Option<String>, Inst)], primitives: Primitives) -> (ZAM, Vec<LinkError>) {
+        let (labels, mut errors) = linker::labels(prog);
+        let code = linker::resolve(prog, &labels, &mut errors);
+        let prim_ids = linker::primitives(prog, &primitives, &mut errors);
 
-        ZAM {
-            prog: prog.clone(),
+        let machine = ZAM {
+            prog: prog.to_vec(),
+            code,
             stack: Vec::new(),
             env: Vec::new(),
             pc: 0,
             accu: Mlvalue::Entier(0),
+            heap: Heap::new(),
             extra_args: 0,
-            labelsMap: labels,
+            trap_sp: None,
+            labels_map: labels,
             options: Vec::new(),
-        }
+            output: Box::new(io::stdout()),
+            primitives,
+            prim_ids,
+            ints: IntMode::default(),
+            tracer: Tracer::default(),
+            steps: 0,
+            max_steps: None,
+            fuel: None,
+            max_stack: None,
+            max_heap: None,
+            profile: None,
+        };
+        (machine, errors)
     }
 
-    pub fn run(&mut self) {
-        let mut run = true;
-        let mut stdin = io::stdin();
-        let mut stdout = io::stdout();
-
-        while run {
-            let inst = self.prog[self.pc as usize].1.clone();
-
-            if self.options.contains(&"Debug".to_string()) {
-                println!("La Stack {:?}", self.stack);
-                println!("L'env {:?}", self.env);
-                println!("L'accu {:?}", self.accu);
-                println!("extras args {:?}", self.extra_args);
-                println!("\nInstruction {} : {:?}", self.pc, inst);
+    pub fn run(&mut self) -> Result<Outcome, VmError> {
+        loop {
+            if let Some(outcome) = self.step()? {
+                return Ok(outcome);
             }
+        }
+    }
 
-            if self.options.contains(&"Step".to_string()) {
-                // We want the cursor to stay at the end of the line, so we print without a newline and flush manually.
-                write!(stdout, "\nPress enter to continue...").unwrap();
-                stdout.flush().unwrap();
+    //exécute l'instruction du pc courant
+    pub fn step(&mut self) -> Result<Option<Outcome>, VmError> {
+        let inst = match self.code.get(self.pc) {
+            Some(inst) => inst.clone(),
+            None => {
+                let error = VmError::new(self.pc, None, ErrorKind::PcOutOfRange);
+                return Err(error.with_backtrace(self.call_chain()));
+            }
+        };
 
-                // Read a single byte and discard
-                let _ = stdin.read(&mut [0u8]).unwrap();
+        if let Some(max) = self.max_steps {
+            if self.steps >= max {
+                let source = self.prog[self.pc].1.clone();
+                let error = VmError::new(self.pc, Some(source), ErrorKind::StepLimit(max));
+                return Err(error.with_backtrace(self.call_chain()));
             }
+        }
+        if self.fuel == Some(0) {
+            self.flush();
+            return Ok(Some(Outcome::OutOfFuel));
+        }
+        self.steps += 1;
+        if let Some(fuel) = &mut self.fuel {
+            *fuel -= 1;
+        }
+        if self.profile.is_some() {
+            self.profile_step(&inst);
+        }
 
-            self.execute(&inst);
+        //les messages ne sont construits que si le niveau est actif
+        if self.tracer.enabled(TraceLevel::State) {
+            let state = format!(
+                "La Stack {:?}\nL'env {:?}\nL'accu {:?}\nextras args {:?}\ntrap sp {:?}",
+                self.stack, self.env, self.accu, self.extra_args, self.trap_sp
+            );
+            self.tracer.emit(TraceLevel::State, &state);
+        }
+        if self.tracer.enabled(TraceLevel::Inst) {
+            let line = format!("Instruction {} : {:?}", self.pc, self.prog[self.pc].1);
+            self.tracer.emit(TraceLevel::Inst, &line);
+        }
 
-            if inst == Inst::Stop {
-                run = false;
+        if self.options.contains(&"Step".to_string()) {
+            if let Err(e) = wait_for_enter() {
+                let source = self.prog[self.pc].1.clone();
+                let error = VmError::new(self.pc, Some(source), ErrorKind::Io(e.to_string()));
+                return Err(error.with_backtrace(self.call_chain()));
             }
         }
+
+        let prim = self.prim_ids.get(self.pc).copied();
+        let mut result = self.execute_with(&inst, prim);
+        if let Ok(None) = result {
+            result = Ok(self.check_limits());
+        }
+        if !matches!(result, Ok(None)) {
+            self.flush();
+        }
+        result
+    }
+
+    //reprend une machine arrêtée par Outcome::OutOfFuel avec un nouveau carburant
+    pub fn resume(&mut self, fuel: u64) -> Result<Outcome, VmError> {
+        self.set_fuel(fuel);
+        self.run()
+    }
+
+    //limite le nombre total d'instructions, l'exécution s'arrête alors sur
+    //l'erreur StepLimit ; set_fuel donne au contraire un budget renouvelable
+    pub fn set_max_steps(&mut self, max: u64) {
+        self.max_steps = Some(max);
+    }
+
+    pub fn set_fuel(&mut self, fuel: u64) {
+        self.fuel = Some(fuel);
+    }
+
+    //nombre de valeurs dans la pile, cadres d'appel et pièges compris, et non
+    //nombre d'appels imbriqués
+    pub fn set_max_stack(&mut self, max: usize) {
+        self.max_stack = Some(max);
     }
 
+    //taille des objets vivants en mots, voir Heap::reserve
+    pub fn set_max_heap(&mut self, max: usize) {
+        self.max_heap = Some(max);
+        self.heap.set_limit(Some(max));
+    }
+
+    //stacks garde aussi les piles d'appels pour le format replié,
+    //au prix d'un parcours de la pile à chaque instruction
+    pub fn enable_profiling(&mut self, stacks: bool) {
+        self.profile = Some(Profile::new(&self.prog, &self.code, stacks));
+    }
+
+    pub fn set_int_mode(&mut self, ints: IntMode) {
+        self.ints = ints;
+    }
+
+    pub fn set_trace(&mut self, level: TraceLevel, sink: TraceSink) {
+        self.tracer = Tracer::new(level, sink);
+    }
+
+    pub fn set_trace_level(&mut self, level: TraceLevel) {
+        self.tracer.level = level;
+    }
+
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    //ajoute ou remplace une primitive, les PRIM du programme sont résolus à nouveau
+    pub fn register_primitive(&mut self, name: &str, arity: usize, native: Native) {
+        self.primitives.register(name, arity, native);
+        self.prim_ids = linker::primitives(&self.prog, &self.primitives, &mut Vec::new());
+    }
+
+    pub fn register_callback(&mut self, name: &str, mut callback: Callback) {
+        let native: Native = Box::new(move |args, context| callback(&args[0], context.heap));
+        self.register_primitive(name, 1, native);
+    }
+
+    pub fn stack(&self) -> &[Mlvalue] {
+        &self.stack
+    }
+
+    pub fn env(&self) -> &[Mlvalue] {
+        &self.env
+    }
+
+    pub fn accu(&self) -> &Mlvalue {
+        &self.accu
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn prog(&self) -> &[(Option<String>, Inst)] {
+        &self.prog
+    }
+
+    pub fn code(&self) -> &[Inst<usize>] {
+        &self.code
+    }
+
+    //indice de l'instruction qui porte le label
+    pub fn label(&self, name: &str) -> Option<usize> {
+        self.labels_map.get(name).copied()
+    }
+
+    pub fn extra_args(&self) -> usize {
+        self.extra_args
+    }
+
+    pub fn trap_sp(&self) -> Option<usize> {
+        self.trap_sp
+    }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn tracer(&self) -> &Tracer {
+        &self.tracer
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    //primitive résolue au chargement pour le PRIM de l'instruction pc
+    pub fn primitive_at(&self, pc: usize) -> Option<&Primitive> {
+        self.prim_ids
+            .get(pc)
+            .and_then(|id| self.primitives.get(*id))
+    }
+
+    //"Debug" trace l'état de la machine, "Step" attend entrée avant chaque
+    //instruction, "Gc" trace les statistiques du GC au STOP
     pub fn set_option(&mut self, option: &str) {
+        if option == "Debug" {
+            self.tracer.level = self.tracer.level.max(TraceLevel::State);
+        }
         if !self.options.contains(&option.to_string()) {
             self.options.push(option.to_string());
         }
     }
 
-    pub fn execute(&mut self, instruction: &Inst) {
+    //renvoie l'issue du programme quand l'instruction arrête la machine,
+    //l'erreur éventuelle cite l'instruction source du pc courant
+    pub fn execute(&mut self, instruction: &Inst<usize>) -> Result<Option<Outcome>, VmError> {
+        self.execute_with(instruction, None)
+    }
+
+    //prim est l'indice résolu au chargement pour un PRIM du programme,
+    //sinon la primitive est cherchée par son nom
+    fn execute_with(
+        &mut self,
+        instruction: &Inst<usize>,
+        prim: Option<usize>,
+    ) -> Result<Option<Outcome>, VmError> {
+        let pc = self.pc;
+        self.exec(instruction, prim).map_err(|kind| {
+            let source = self.prog.get(pc).map(|(_, inst)| inst.clone());
+            VmError::new(pc, source, kind).with_backtrace(self.call_chain())
+        })
+    }
+
+    fn exec(
+        &mut self,
+        instruction: &Inst<usize>,
+        prim: Option<usize>,
+    ) -> Result<Option<Outcome>, ErrorKind> {
         match instruction {
             Inst::Const(n) => {
                 self.accu = Mlvalue::Entier(*n);
                 self.pc += 1;
             }
+            Inst::ConstString(s) => {
+                if self.heap.should_collect() {
+                    self.collect();
+                }
+                self.accu = self.heap.alloc_string(s.as_bytes().to_vec());
+                self.pc += 1;
+            }
+            Inst::ConstFloat(x) => {
+                if self.heap.should_collect() {
+                    self.collect();
+                }
+                self.accu = self.heap.alloc_float(*x);
+                self.pc += 1;
+            }
             Inst::Prim(op) => {
-                match op.as_str() {
-                    "+" => {
-                        let a0: i64 = self.stack.pop().unwrap().as_int();
-                        let result = self.accu.as_int() + a0;
-                        self.accu = Mlvalue::Entier(result);
-                    }
-                    "/" => {
-                        let a0: i64 = self.stack.pop().unwrap().as_int();
-                        let result = self.accu.as_int() / a0;
-                        self.accu = Mlvalue::Entier(result);
-                    }
-                    "-" => {
-                        let a0: i64 = self.stack.pop().unwrap().as_int();
-                        let result = self.accu.as_int() - a0;
-                        self.accu = Mlvalue::Entier(result);
-                    }
-                    "*" => {
-                        let a0: i64 = self.stack.pop().unwrap().as_int();
-                        let result = self.accu.as_int() * a0;
-                        self.accu = Mlvalue::Entier(result);
+                let id = prim
+                    .or_else(|| self.primitives.id(op))
+                    .unwrap_or(UNRESOLVED);
+                let arity = match self.primitives.get(id) {
+                    Some(primitive) => primitive.arity,
+                    None => return Err(ErrorKind::UnknownPrimitive(op.clone())),
+                };
+                //l'accu est le premier argument
+                let mut args = Vec::with_capacity(arity);
+                if arity > 0 {
+                    args.push(self.accu.clone());
+                    for _ in 1..arity {
+                        args.push(self.pop()?);
                     }
-                    "not" => {
-                        let result = !self.accu.as_bool();
-                        self.accu = Mlvalue::to_entier(result);
-                    }
-                    "<" => {
-                        let a0 = self.stack.pop().unwrap().as_int();
-                        let result = self.accu.as_int() < a0;
-                        self.accu = Mlvalue::to_entier(result);
-                    }
-                    ">" => {
-                        let a0 = self.stack.pop().unwrap().as_int();
-                        let result = self.accu.as_int() > a0;
-                        self.accu = Mlvalue::to_entier(result);
-                    }
-                    "=" => {
-                        let a0 = self.stack.pop().unwrap().as_int();
-                        let result = self.accu.as_int() == a0;
-                        self.accu = Mlvalue::to_entier(result);
-                    }
-                    "<=" => {
-                        let a0 = self.stack.pop().unwrap().as_int();
-                        let result = self.accu.as_int() <= a0;
-                        self.accu = Mlvalue::to_entier(result);
-                    }
-                    ">=" => {
-                        let a0 = self.stack.pop().unwrap().as_int();
-                        let result = self.accu.as_int() >= a0;
-                        self.accu = Mlvalue::to_entier(result);
-                    }
-                    "or" => {
-                        let a0 = self.stack.pop().unwrap().as_bool();
-                        let result = self.accu.as_bool() || a0;
-                        self.accu = Mlvalue::to_entier(result);
-                    }
-                    "and" => {
-                        let a0 = self.stack.pop().unwrap().as_bool();
-                        let result = self.accu.as_bool() && a0;
-                        self.accu = Mlvalue::to_entier(result);
+                }
+                let mut result = self.call_primitive(id, &args);
+                if result == Err(ErrorKind::HeapExhausted) {
+                    //les arguments dépilés restent des racines le temps de réessayer
+                    let roots = self.stack.iter().chain(self.env.iter());
+                    self.heap.collect(roots.chain(args.iter()));
+                    result = self.call_primitive(id, &args);
+                }
+                match result {
+                    Ok(v) => self.accu = v,
+                    //la machine reste arrêtée sur le PRIM, arguments rempilés
+                    Err(ErrorKind::HeapExhausted) => {
+                        self.stack.extend(args.into_iter().skip(1).rev());
+                        return Ok(Some(Outcome::HeapExhausted));
                     }
-                    "print" => {
-                        let val = self.accu.as_int();
-                        print!("{}", (val as u8) as char)
+                    //division par zéro rattrapable par le programme, comme en OCaml
+                    Err(ErrorKind::DivisionByZero) => {
+                        self.accu = primitives::exception(&mut self.heap, "Division_by_zero");
+                        return self.raise();
                     }
-                    _ => {
-                        panic!("Operation non supportee");
+                    Err(ErrorKind::Raise(exn)) => {
+                        self.accu = exn;
+                        return self.raise();
                     }
+                    Err(e) => return Err(e),
+                }
+                //les arguments ne sont plus des racines, seul l'accu compte
+                if self.heap.should_collect() {
+                    self.collect();
                 }
                 self.pc += 1;
             }
-            Inst::Branch(l) => self.pc = *self.labelsMap.get(&l.clone()).unwrap(),
+            Inst::Branch(l) => self.pc = self.target(*l)?,
             Inst::BranchIfNot(l) => {
                 if self.accu == Mlvalue::Entier(0) {
-                    self.pc = *self.labelsMap.get(&l.clone()).unwrap();
+                    self.pc = self.target(*l)?;
                 } else {
                     self.pc += 1;
                 }
             }
+            Inst::Switch(consts, tags) => {
+                //indice du cas parmi les cibles, les entiers d'abord
+                let case = match self.accu {
+                    Mlvalue::Entier(n) if n >= 0 && (n as usize) < consts.len() => n as usize,
+                    Mlvalue::Entier(n) => {
+                        return Err(ErrorKind::BadOperand(format!(
+                            "SWITCH sans cas pour l'entier {}",
+                            n
+                        )))
+                    }
+                    _ => {
+                        let tag = self.accu.tag(&self.heap)? as usize;
+                        if tag >= tags.len() {
+                            return Err(ErrorKind::BadOperand(format!(
+                                "SWITCH sans cas pour l'étiquette {}",
+                                tag
+                            )));
+                        }
+                        consts.len() + tag
+                    }
+                };
+                let target = consts.iter().chain(tags).nth(case).unwrap();
+                self.pc = self.target_at(*target, case)?;
+            }
             Inst::Push => {
                 self.stack.push(self.accu.clone());
-                match self.accu {
-                    Mlvalue::Block(_) => self.accu = Mlvalue::RefBlock(self.stack.len() - 1),
-                    _ => {}
-                }
                 self.pc += 1;
             }
             Inst::Pop => {
-                self.stack.pop();
+                self.pop()?;
                 self.pc += 1;
             }
             Inst::Acc(i) => {
-                let indice: usize = self.stack.len() - (*i as usize) - 1;
-                match self.stack[indice] {
-                    Mlvalue::Block(_) => self.accu = Mlvalue::RefBlock(indice),
-                    _ => {
-                        self.accu = self.stack[indice].clone();
-                    }
-                }
+                let indice = self.stack_index(*i as usize)?;
+                self.accu = self.stack[indice].clone();
                 self.pc += 1;
             }
             Inst::Envacc(i) => {
-                match self.env[*i as usize] {
-                    Mlvalue::Block(_) => self.accu = Mlvalue::RefBlock(*i as usize),
-                    _ => {
-                        self.accu = self.stack[*i as usize].clone();
+                self.accu = match self.env.get(*i as usize) {
+                    Some(v) => v.clone(),
+                    None => {
+                        return Err(ErrorKind::BadFieldIndex {
+                            index: *i as i64,
+                            size: self.env.len(),
+                        })
                     }
-                }
+                };
                 self.pc += 1;
             }
             Inst::Closure(l, n) => {
@@ -231,69 +687,73 @@ impl ZAM {
                     let acc = self.accu.clone();
                     self.stack.push(acc);
                 }
-                let posl = *self.labelsMap.get(&l.clone()).unwrap();
+                let posl = self.target(*l)?;
                 let mut nenv = Vec::new();
                 for _ in 0..*n {
-                    nenv.push(self.stack.pop().unwrap());
+                    nenv.push(self.pop()?);
                 }
                 self.accu = Mlvalue::Fermeture(posl, nenv);
                 self.pc += 1;
             }
             Inst::Apply(n) => {
-                if *n < 0 {
-                    panic!("Nombre D'arguments négatifs");
+                if *n < 1 {
+                    return Err(ErrorKind::BadOperand(
+                        "au moins un argument attendu".to_string(),
+                    ));
                 }
+                //vérifié avant d'empiler le cadre de retour
+                self.check_accu_closure()?;
                 let mut tmp = Vec::new();
                 for _ in 0..*n {
-                    tmp.push(self.stack.pop().unwrap());
+                    tmp.push(self.pop()?);
                 }
                 //empilé dans l'ordre env, pc , extra args
                 self.stack.push(Mlvalue::Environement(self.env.clone()));
                 self.stack.push(Mlvalue::Entier((self.pc + 1) as i64));
                 self.stack.push(Mlvalue::Entier(self.extra_args as i64));
 
-                for i in 0..*n {
-                    let val = tmp[i as usize].clone();
+                //le premier argument reste au sommet de la pile
+                while let Some(val) = tmp.pop() {
                     self.stack.push(val);
                 }
 
-                match &self.accu {
-                    Mlvalue::Fermeture(npc, nenv) => {
-                        self.pc = *npc;
-                        self.env = nenv.clone();
-                    }
-                    _ => panic!("Pas de Fermeture dans l'Accu"),
-                }
+                self.jump_to_accu()?;
 
                 self.extra_args = (*n as usize) - 1;
             }
             Inst::Return(n) => {
                 if *n < 0 {
-                    panic!("Nombre D'Arguments Négatifs");
+                    return Err(ErrorKind::BadOperand(
+                        "nombre d'arguments négatif".to_string(),
+                    ));
                 }
 
                 for _ in 0..*n {
-                    self.stack.pop();
+                    self.pop()?;
                 }
 
                 if self.extra_args == 0 {
                     //dépilé dans l'ordre inverse a apply extra args,pc,env
-                    self.extra_args = self.stack.pop().unwrap().as_int() as usize;
-                    self.pc = self.stack.pop().unwrap().as_int() as usize;
-                    self.env = self.stack.pop().unwrap().as_env();
+                    self.extra_args = self.pop()?.as_int()? as usize;
+                    self.pc = self.pop()?.as_int()? as usize;
+                    self.env = self.pop()?.as_env()?;
                 } else {
                     self.extra_args -= 1;
-                    match &self.accu {
-                        Mlvalue::Fermeture(npc, nenv) => {
-                            self.pc = *npc;
-                            self.env = nenv.clone();
-                        }
-                        _ => panic!("Not a Fermeture"),
-                    }
+                    self.jump_to_accu()?;
                 }
             }
             Inst::Stop => {
-                println!("Valeur de fin de Code {:?}", self.accu);
+                let fin = format!("Valeur de fin de Code {}", self.heap.show(&self.accu));
+                self.tracer.emit(TraceLevel::Result, &fin);
+                if self.options.contains(&"Gc".to_string()) {
+                    let gc = format!(
+                        "GC : {}, taille du tas {}",
+                        self.heap.stats(),
+                        self.heap.size()
+                    );
+                    self.tracer.emit(TraceLevel::Result, &gc);
+                }
+                return Ok(Some(Outcome::Value(self.accu.clone())));
             }
             //Fonctions Recursives
             Inst::ClosureRec(l, n) => {
@@ -301,18 +761,22 @@ impl ZAM {
                     let acc = self.accu.clone();
                     self.stack.push(acc);
                 }
-                let posl = *self.labelsMap.get(&l.clone()).unwrap();
+                let posl = self.target(*l)?;
                 let mut nenv = Vec::new();
                 nenv.push(Mlvalue::Entier(posl as i64));
                 for _ in 0..*n {
-                    nenv.push(self.stack.pop().unwrap());
+                    nenv.push(self.pop()?);
                 }
                 self.accu = Mlvalue::Fermeture(posl, nenv);
                 self.stack.push(self.accu.clone());
                 self.pc += 1;
             }
             Inst::OffSetClosure => {
-                self.accu = Mlvalue::Fermeture(self.env[0].as_int() as usize, self.env.clone());
+                let posl = match self.env.first() {
+                    Some(v) => v.as_int()? as usize,
+                    None => return Err(ErrorKind::BadFieldIndex { index: 0, size: 0 }),
+                };
+                self.accu = Mlvalue::Fermeture(posl, self.env.clone());
                 self.pc += 1;
             }
             //Fonctions n aires
@@ -321,109 +785,407 @@ impl ZAM {
                     self.extra_args -= *n as usize;
                     self.pc += 1;
                 } else {
+                    //l'env de la fermeture partielle est l'env courant suivi des arguments
+                    let mut nenv = vec![Mlvalue::Environement(self.env.clone())];
                     for _ in 0..self.extra_args + 1 {
-                        self.env.push(self.stack.pop().unwrap());
+                        nenv.push(self.pop()?);
                     }
-                    self.accu = Mlvalue::Fermeture(self.pc - 1, self.env.clone());
+                    //le RESTART qui précède le GRAB reprend l'application
+                    let restart = self.pc.checked_sub(1).ok_or(ErrorKind::PcOutOfRange)?;
+                    self.accu = Mlvalue::Fermeture(restart, nenv);
 
-                    self.extra_args = self.stack.pop().unwrap().as_int() as usize;
-                    self.pc = self.stack.pop().unwrap().as_int() as usize;
-                    self.env = self.stack.pop().unwrap().as_env();
+                    self.extra_args = self.pop()?.as_int()? as usize;
+                    self.pc = self.pop()?.as_int()? as usize;
+                    self.env = self.pop()?.as_env()?;
                 }
             }
             Inst::Restart => {
                 let n = self.env.len();
-                if n > 0 {
-                    for i in 1..n {
-                        self.stack.push(self.env[i].clone());
-                    }
-                    self.env = vec![self.env[0].clone()];
-                    self.extra_args += (n - 1);
-                } else {
-                    self.extra_args -= 1;
+                if n == 0 {
+                    return Err(ErrorKind::BadFieldIndex { index: 0, size: 0 });
+                }
+                for i in (1..n).rev() {
+                    self.stack.push(self.env[i].clone());
                 }
+                self.env = self.env[0].as_env()?;
+                self.extra_args += n - 1;
 
                 self.pc += 1;
             }
             Inst::AppTerm(n, m) => {
                 if *n > *m {
-                    panic!("Erreur n > m");
+                    return Err(ErrorKind::BadOperand(format!("{} > {}", n, m)));
                 }
-                if *n < 0 {
-                    panic!("Nombre D'arguments négatifs");
+                if *n < 1 {
+                    return Err(ErrorKind::BadOperand(
+                        "au moins un argument attendu".to_string(),
+                    ));
                 }
+                self.check_accu_closure()?;
                 let mut tmp = Vec::new();
                 for _ in 0..*n {
-                    tmp.push(self.stack.pop().unwrap());
+                    tmp.push(self.pop()?);
                 }
                 for _ in *n..*m {
-                    self.stack.pop().unwrap();
+                    self.pop()?;
                 }
-                for i in 0..*n {
-                    let val = tmp[i as usize].clone();
+                //le premier argument reste au sommet de la pile
+                while let Some(val) = tmp.pop() {
                     self.stack.push(val);
                 }
-                match &self.accu {
-                    Mlvalue::Fermeture(npc, nenv) => {
-                        self.pc = *npc;
-                        self.env = nenv.clone();
-                    }
-                    _ => panic!("Pas de Fermeture dans l'Accu"),
-                }
+                self.jump_to_accu()?;
 
                 self.extra_args += (*n as usize) - 1;
             }
             //blocks mutables
-            Inst::Makeblock(n) => {
+            Inst::Makeblock(n, tag) => {
                 if *n < 0 {
-                    panic!("Taille négative");
+                    return Err(ErrorKind::BadOperand("taille négative".to_string()));
+                }
+                if *tag < 0 || *tag > mlvalue::MAX_BLOCK_TAG as i64 {
+                    return Err(ErrorKind::BadOperand(format!("étiquette {}", tag)));
+                }
+                //les champs autres que l'accu doivent être dans la pile
+                if *n > 1 && (*n - 1) as u64 > self.stack.len() as u64 {
+                    return Err(ErrorKind::StackUnderflow);
+                }
+                //le bloc et ses champs encore dans la pile restent des racines
+                if self.heap.should_collect() {
+                    self.collect();
                 }
                 let mut block = Vec::with_capacity(*n as usize);
                 if *n > 0 {
                     block.push(self.accu.clone());
-                    for i in 1..*n {
-                        block.push(self.stack.pop().unwrap());
+                    for _ in 1..*n {
+                        block.push(self.pop()?);
                     }
                 }
-                self.accu = Mlvalue::Block(block);
+                self.accu = self.heap.alloc_tagged(*tag as u8, block);
+                self.pc += 1;
+            }
+            Inst::GetTag => {
+                self.accu = Mlvalue::Entier(self.accu.tag(&self.heap)? as i64);
                 self.pc += 1;
             }
             Inst::Getfield(n) => {
-                self.accu = self.accu.getfield(*n as usize, & mut self.stack);
+                self.accu = self.accu.getfield(*n as usize, &self.heap)?;
                 self.pc += 1;
             }
 
             Inst::Veclength => {
-                self.accu = Mlvalue::Entier(self.accu.veclen(& self.stack) as i64);
+                self.accu = Mlvalue::Entier(self.accu.veclen(&self.heap)? as i64);
                 self.pc += 1;
             }
 
             Inst::Getvectitem => {
-                let n = self.stack.pop().unwrap().as_int();
-                self.accu = self.accu.getfield(n as usize,&mut  self.stack);
+                let n = self.pop()?.as_int()?;
+                self.accu =
+                    match self.accu {
+                        //l'élément est mis dans une nouvelle boîte
+                        Mlvalue::Flottants(h) => {
+                            if self.heap.should_collect() {
+                                self.collect();
+                            }
+                            let floats = self.heap.get_floats(h)?;
+                            let x = floats.get(n as usize).copied().ok_or(
+                                ErrorKind::BadFieldIndex {
+                                    index: n,
+                                    size: floats.len(),
+                                },
+                            )?;
+                            self.heap.alloc_float(x)
+                        }
+                        _ => self.accu.getfield(n as usize, &self.heap)?,
+                    };
                 self.pc += 1;
             }
 
             Inst::Setfield(n) => {
-                let val = self.stack.pop().unwrap();
-                self.accu.setfield(*n as usize, val, &mut self.stack);
+                let val = self.pop()?;
+                self.accu.setfield(*n as usize, val, &mut self.heap)?;
+                self.accu = Mlvalue::Entier(0);
                 self.pc += 1;
             }
 
             Inst::SetVectitem => {
-                let n = self.stack.pop().unwrap().as_int();
-                let v = self.stack.pop().unwrap();
-                self.accu.setfield(n as usize, v, &mut self.stack);
-                self.accu=Mlvalue::Entier(0);
+                let n = self.pop()?.as_int()?;
+                let v = self.pop()?;
+                match self.accu {
+                    Mlvalue::Flottants(h) => {
+                        let x = v.as_float(&self.heap)?;
+                        let floats = self.heap.get_floats_mut(h)?;
+                        let size = floats.len();
+                        match floats.get_mut(n as usize) {
+                            Some(slot) => *slot = x,
+                            None => return Err(ErrorKind::BadFieldIndex { index: n, size }),
+                        }
+                    }
+                    _ => self.accu.setfield(n as usize, v, &mut self.heap)?,
+                }
+                self.accu = Mlvalue::Entier(0);
                 self.pc += 1;
             }
 
             Inst::Assign(n) => {
-                let indice: usize = self.stack.len() - (*n as usize) - 1;
+                let indice = self.stack_index(*n as usize)?;
                 self.stack[indice] = self.accu.clone();
                 self.accu = Mlvalue::Entier(0);
                 self.pc += 1;
             }
+            //exceptions
+            Inst::PushTrap(l) => {
+                //empilé dans l'ordre extra args, env, trap sp, rattrapeur
+                let handler = self.target(*l)?;
+                let trap_sp = self.trap_sp.map_or(-1, |sp| sp as i64);
+                self.stack.push(Mlvalue::Entier(self.extra_args as i64));
+                self.stack.push(Mlvalue::Environement(self.env.clone()));
+                self.stack.push(Mlvalue::Entier(trap_sp));
+                self.stack.push(Mlvalue::Entier(handler as i64));
+                self.trap_sp = Some(self.stack.len());
+                self.pc += 1;
+            }
+            Inst::PopTrap => {
+                self.pop()?;
+                self.trap_sp = self.pop_trap_sp()?;
+                self.pop()?;
+                self.pop()?;
+                self.pc += 1;
+            }
+            Inst::Raise => return self.raise(),
+        }
+        Ok(None)
+    }
+
+    //lève l'exception de l'accu vers le rattrapeur courant
+    fn raise(&mut self) -> Result<Option<Outcome>, ErrorKind> {
+        match self.trap_sp {
+            None => {
+                let msg = format!(
+                    "Exception non rattrapée {}\n{}",
+                    self.heap.show(&self.accu),
+                    error::format_backtrace(&self.call_chain()).trim_end()
+                );
+                self.tracer.emit(TraceLevel::Result, &msg);
+                Ok(Some(Outcome::Exception(self.accu.clone())))
+            }
+            Some(sp) => {
+                //un cadre de rattrapage corrompu ne doit pas être suivi
+                if sp > self.stack.len() {
+                    return Err(ErrorKind::StackUnderflow);
+                }
+                self.stack.truncate(sp);
+                self.pc = self.pop()?.as_int()? as usize;
+                self.trap_sp = self.pop_trap_sp()?;
+                self.env = self.pop()?.as_env()?;
+                self.extra_args = self.pop()?.as_int()? as usize;
+                Ok(None)
+            }
+        }
+    }
+
+    //la première activation est celle du pc courant, les suivantes sont
+    //reconstruites depuis les triplets env, pc, extra args empilés par APPLY
+    //(les appels terminaux APPTERM n'en laissent pas)
+    pub fn backtrace(&self) -> Vec<Frame> {
+        let mut traps = Vec::new();
+        let mut sp = self.trap_sp;
+        while let Some(top) = sp {
+            if top < 4 {
+                break;
+            }
+            traps.push(top - 4..top);
+            sp = match self.stack.get(top - 2) {
+                Some(Mlvalue::Entier(prev)) if *prev >= 0 && (*prev as usize) < top => {
+                    Some(*prev as usize)
+                }
+                _ => None,
+            };
+        }
+
+        let mut frames = vec![Frame {
+            pc: self.pc,
+            sp: self.stack.len(),
+            env: self.env.clone(),
+            extra_args: self.extra_args,
+        }];
+        let mut i = self.stack.len();
+        while i >= 3 {
+            let frame = match &self.stack[i - 3..i] {
+                [Mlvalue::Environement(env), Mlvalue::Entier(ret), Mlvalue::Entier(extra)]
+                    if !traps.iter().any(|t| t.contains(&(i - 1))) =>
+                {
+                    let call = (*ret as usize).wrapping_sub(1);
+                    match self.code.get(call) {
+                        Some(Inst::Apply(_)) if *ret > 0 && *extra >= 0 => Some(Frame {
+                            pc: call,
+                            sp: i - 3,
+                            env: env.clone(),
+                            extra_args: *extra as usize,
+                        }),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            match frame {
+                Some(frame) => {
+                    frames.push(frame);
+                    i -= 3;
+                }
+                None => i -= 1,
+            }
+        }
+        frames
+    }
+
+    fn profile_step(&mut self, inst: &Inst<usize>) {
+        let callee = match (inst, &self.accu) {
+            (Inst::Apply(_), Mlvalue::Fermeture(pc, _))
+            | (Inst::AppTerm(_, _), Mlvalue::Fermeture(pc, _)) => Some(*pc),
+            _ => None,
+        };
+        let chain: Vec<usize> = match &self.profile {
+            Some(profile) if profile.stacks.is_some() => {
+                self.backtrace().iter().map(|frame| frame.pc).collect()
+            }
+            _ => Vec::new(),
+        };
+        if let Some(profile) = &mut self.profile {
+            profile.record(self.pc, inst, callee, &chain);
+        }
+    }
+
+    //vérifiées après chaque instruction, le tas n'est déclaré plein
+    //qu'après une collection
+    fn check_limits(&mut self) -> Option<Outcome> {
+        if let Some(max) = self.max_stack {
+            if self.stack.len() > max {
+                return Some(Outcome::StackOverflow);
+            }
+        }
+        if let Some(max) = self.max_heap {
+            //le tas ne se vide qu'au rythme habituel du ramasse-miettes, la
+            //limite porte sur ce qui survit au ramassage
+            if self.heap.stats().words > max && self.heap.should_collect() {
+                self.collect();
+            }
+            if self.heap.over_limit() {
+                return Some(Outcome::HeapExhausted);
+            }
+        }
+        None
+    }
+
+    fn flush(&mut self) {
+        let _ = self.output.flush();
+        self.tracer.flush();
+    }
+
+    pub fn call_chain(&self) -> Backtrace {
+        self.backtrace()
+            .iter()
+            .map(|frame| (frame.pc, self.locate(frame.pc)))
+            .collect()
+    }
+
+    //pc sous la forme label+décalage d'après le label précédent le plus proche
+    pub fn locate(&self, pc: usize) -> String {
+        let start = pc.min(self.prog.len());
+        for i in (0..start + 1).rev() {
+            if let Some((Some(l), _)) = self.prog.get(i) {
+                return match pc - i {
+                    0 => l.clone(),
+                    offset => format!("{}+{}", l, offset),
+                };
+            }
+        }
+        pc.to_string()
+    }
+
+    //les racines sont la pile, l'env et l'accu
+    pub fn collect(&mut self) {
+        let roots = self.stack.iter().chain(self.env.iter());
+        self.heap.collect(roots.chain(std::iter::once(&self.accu)));
+    }
+
+    fn pop(&mut self) -> Result<Mlvalue, ErrorKind> {
+        self.stack.pop().ok_or(ErrorKind::StackUnderflow)
+    }
+
+    //indice dans le vecteur de la i-ème case depuis le sommet de pile
+    fn stack_index(&self, i: usize) -> Result<usize, ErrorKind> {
+        if i < self.stack.len() {
+            Ok(self.stack.len() - i - 1)
+        } else {
+            Err(ErrorKind::StackUnderflow)
+        }
+    }
+
+    //cible liée d'un saut, le nom du label manquant est repris du source
+    fn target(&self, target: usize) -> Result<usize, ErrorKind> {
+        self.target_at(target, 0)
+    }
+
+    //la cible est la n-ième de l'instruction courante, pour nommer le label
+    fn target_at(&self, target: usize, n: usize) -> Result<usize, ErrorKind> {
+        if target != UNRESOLVED {
+            return Ok(target);
         }
+        let label = self
+            .prog
+            .get(self.pc)
+            .and_then(|(_, inst)| inst.targets().get(n).map(|l| l.to_string()))
+            .unwrap_or_default();
+        Err(ErrorKind::UnknownLabel(label))
     }
+
+    fn check_accu_closure(&self) -> Result<(), ErrorKind> {
+        match &self.accu {
+            Mlvalue::Fermeture(_, _) => Ok(()),
+            _ => Err(ErrorKind::TypeMismatch {
+                expected: "Fermeture",
+                found: self.accu.clone(),
+            }),
+        }
+    }
+
+    fn call_primitive(&mut self, id: usize, args: &[Mlvalue]) -> Result<Mlvalue, ErrorKind> {
+        let mut context = Context {
+            heap: &mut self.heap,
+            output: &mut *self.output,
+            ints: self.ints,
+        };
+        self.primitives.call(id, args, &mut context)
+    }
+
+    fn jump_to_accu(&mut self) -> Result<(), ErrorKind> {
+        match &self.accu {
+            Mlvalue::Fermeture(npc, nenv) => {
+                self.pc = *npc;
+                self.env = nenv.clone();
+                Ok(())
+            }
+            _ => Err(ErrorKind::TypeMismatch {
+                expected: "Fermeture",
+                found: self.accu.clone(),
+            }),
+        }
+    }
+
+    fn pop_trap_sp(&mut self) -> Result<Option<usize>, ErrorKind> {
+        match self.pop()?.as_int()? {
+            -1 => Ok(None),
+            sp => Ok(Some(sp as usize)),
+        }
+    }
+}
+
+//le curseur reste en fin de ligne jusqu'à l'appui sur entrée
+fn wait_for_enter() -> io::Result<()> {
+    let mut stderr = io::stderr();
+    write!(stderr, "\nPress enter to continue...")?;
+    stderr.flush()?;
+    //en fin d'entrée on continue sans attendre
+    let _read = io::stdin().read(&mut [0u8])?;
+    Ok(())
 }
@@ -1,27 +1,376 @@
-use std::thread;
-use std::time::Duration;
+use mini_zam::error::format_backtrace;
+use mini_zam::linker;
+use mini_zam::optimizer::PASS_NAMES;
+use mini_zam::{
+    bytecode, disasm, load_file, optimize, write_bytecode, Debugger, DisasmOptions, ErrorKind,
+    IntMode, Outcome, Passes, Primitives, ProfileFormat, Program, TraceLevel, TraceSink, ZAM,
+};
 
-use std::io;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::process;
 
-mod machine;
-mod mlvalue;
-mod parser;
+//codes de sortie du processus
+const EXIT_OK: i32 = 0;
+const EXIT_EXCEPTION: i32 = 1;
+const EXIT_VM_ERROR: i32 = 2;
+const EXIT_LOAD_ERROR: i32 = 3;
+const EXIT_LIMIT: i32 = 4;
+const EXIT_USAGE: i32 = 64;
 
-fn main() {
-    let arguments = std::env::args().collect::<Vec<String>>();
+const USAGE: &str = "usage : Mini-ZAM [run|disasm|check|trace|debug|convert] [options] <fichier>
+
+commandes :
+  run       exécute le programme (par défaut)
+  disasm    affiche le code chargé dans le format texte, avec l'indice
+            des instructions et l'arité des fermetures en commentaire
+  check     vérifie que le programme se charge
+  trace     exécute en affichant l'état de la machine à chaque instruction
+  debug     lance le débogueur interactif (help pour la liste des commandes)
+  convert   convertit du texte (ou du mini-ML) en bytecode binaire, et
+            du bytecode binaire en texte ; -o donne le fichier produit
+
+options :
+  --debug          affiche l'état de la machine à chaque instruction
+  --step           attend la touche entrée avant chaque instruction
+  --gc             affiche les statistiques du GC à la fin
+  --no-appterm     n'applique pas la transformation AppTerm
+  --no-opt <passe> désactive une optimisation : appterm, push-pop, jumps,
+                   dead-code, ou all pour toutes
+  --ints <mode>    arithmétique entière : native (64 bits, par défaut) ou
+                   ocaml (63 bits comme OCaml)
+  --max-steps <n>  arrête la machine après n instructions
+  --max-stack <n>  arrête la machine quand la pile dépasse n valeurs
+  --max-heap <n>   arrête la machine quand les objets vivants dépassent n mots
+                   de 8 octets
+  --trace <niveau> niveau des diagnostics : off, result, inst ou state
+  --trace-file <f> écrit les diagnostics dans f plutôt que sur la sortie d'erreur
+  --profile <fmt>  compte les instructions exécutées par sorte, par pc et par
+                   fonction, et les appels ; le rapport est écrit à la fin au
+                   format table, json ou folded (pour flamegraph)
+  -o <fichier>     fichier produit par convert, ou rapport du profileur
+  --plain          disasm n'écrit ni indices ni annotations
+  --renumber       disasm nomme chaque label d'après l'indice de sa cible
+  --strip          convert n'écrit pas les labels dans le bytecode binaire
+  -h, --help       affiche cette aide
+
+code de sortie : 0 succès, 1 exception non rattrapée, 2 erreur de la machine,
+3 erreur de chargement, 4 limite atteinte (instructions, carburant, pile ou
+tas), 64 mauvaise utilisation";
+
+#[derive(PartialEq)]
+enum Command {
+    Run,
+    Disasm,
+    Check,
+    Trace,
+    Debug,
+    Convert,
+}
+
+struct Options {
+    command: Command,
+    file: String,
+    debug: bool,
+    step: bool,
+    gc: bool,
+    passes: Passes,
+    ints: IntMode,
+    max_steps: Option<u64>,
+    max_stack: Option<usize>,
+    max_heap: Option<usize>,
+    trace: Option<TraceLevel>,
+    trace_file: Option<String>,
+    profile: Option<ProfileFormat>,
+    output: Option<String>,
+    symbols: bool,
+    disasm: DisasmOptions,
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("{}\n\n{}", msg, USAGE);
+    process::exit(EXIT_USAGE);
+}
+
+fn parse_args(arguments: &[String]) -> Options {
+    let mut args = arguments.iter().peekable();
+    let command = match args.peek().map(|a| a.as_str()) {
+        Some("run") => Some(Command::Run),
+        Some("disasm") => Some(Command::Disasm),
+        Some("check") => Some(Command::Check),
+        Some("trace") => Some(Command::Trace),
+        Some("debug") => Some(Command::Debug),
+        Some("convert") => Some(Command::Convert),
+        _ => None,
+    };
+    if command.is_some() {
+        args.next();
+    }
+
+    let mut options = Options {
+        command: command.unwrap_or(Command::Run),
+        file: String::new(),
+        debug: false,
+        step: false,
+        gc: false,
+        passes: Passes::default(),
+        ints: IntMode::default(),
+        max_steps: None,
+        max_stack: None,
+        max_heap: None,
+        trace: None,
+        trace_file: None,
+        profile: None,
+        output: None,
+        symbols: true,
+        disasm: DisasmOptions {
+            indices: true,
+            synthesize_labels: false,
+            annotate: true,
+        },
+    };
+    let mut file = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(EXIT_OK);
+            }
+            "--debug" => options.debug = true,
+            "--step" => options.step = true,
+            "--gc" => options.gc = true,
+            "--no-appterm" => options.passes.appterm = false,
+            "--no-opt" => match args.next().map(|p| p.as_str()) {
+                Some("all") => options.passes = Passes::none(),
+                Some(pass) if options.passes.set(pass, false) => {}
+                _ => usage_error(&format!("--no-opt attend all ou {}", PASS_NAMES.join(", "))),
+            },
+            "--ints" => match args.next().and_then(|m| IntMode::from_name(m)) {
+                Some(ints) => options.ints = ints,
+                _ => usage_error("--ints attend native ou ocaml"),
+            },
+            "--max-steps" => match args.next().map(|n| n.parse::<u64>()) {
+                Some(Ok(n)) => options.max_steps = Some(n),
+                _ => usage_error("--max-steps attend un nombre"),
+            },
+            "--max-stack" => match args.next().map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) => options.max_stack = Some(n),
+                _ => usage_error("--max-stack attend un nombre"),
+            },
+            "--max-heap" => match args.next().map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) => options.max_heap = Some(n),
+                _ => usage_error("--max-heap attend un nombre"),
+            },
+            "--trace" => match args.next().and_then(|l| TraceLevel::from_name(l)) {
+                Some(level) => options.trace = Some(level),
+                _ => usage_error("--trace attend off, result, inst ou state"),
+            },
+            "--trace-file" => match args.next() {
+                Some(f) => options.trace_file = Some(f.clone()),
+                None => usage_error("--trace-file attend un fichier"),
+            },
+            "--profile" => match args.next().and_then(|f| ProfileFormat::from_name(f)) {
+                Some(format) => options.profile = Some(format),
+                _ => usage_error("--profile attend table, json ou folded"),
+            },
+            "-o" => match args.next() {
+                Some(f) => options.output = Some(f.clone()),
+                None => usage_error("-o attend un fichier"),
+            },
+            "--strip" => options.symbols = false,
+            "--plain" => {
+                options.disasm.indices = false;
+                options.disasm.annotate = false;
+            }
+            "--renumber" => options.disasm.synthesize_labels = true,
+            _ if arg.starts_with('-') => usage_error(&format!("option inconnue {}", arg)),
+            _ if file.is_some() => usage_error(&format!("argument en trop {}", arg)),
+            _ => file = Some(arg.clone()),
+        }
+    }
+    match file {
+        Some(f) => options.file = f,
+        None => usage_error("fichier manquant"),
+    }
+    if options.command == Command::Convert && options.output.is_none() {
+        usage_error("convert attend -o <fichier>");
+    }
+    options
+}
+
+fn load(options: &Options) -> Program {
+    let code = match load_file(&options.file) {
+        //convert garde le programme tel quel
+        Ok(code) if options.command == Command::Convert => return code,
+        Ok(code) => optimize(&code, &options.passes),
+        Err(e) => {
+            eprintln!("Impossible de charger {} : {}", options.file, e);
+            process::exit(EXIT_LOAD_ERROR);
+        }
+    };
+    //seules les primitives prédéfinies existent en ligne de commande
+    if let Err(errors) = linker::check_primitives(&code, &Primitives::new()) {
+        for e in errors {
+            eprintln!("Impossible de charger {} : {}", options.file, e);
+        }
+        process::exit(EXIT_LOAD_ERROR);
+    }
+    code
+}
+
+fn convert(options: &Options, code: &Program) -> i32 {
+    let mut start = [0u8; 4];
+    let binary = File::open(&options.file)
+        .and_then(|mut f| f.read_exact(&mut start))
+        .is_ok()
+        && bytecode::is_bytecode(&start);
 
-    let mut code = parser::parse_prog(parser::reader(arguments[1].as_str()));
+    let output = options.output.as_deref().unwrap_or_default();
+    let mut out = match File::create(output) {
+        Ok(f) => BufWriter::new(f),
+        Err(e) => {
+            eprintln!("Impossible de créer {} : {}", output, e);
+            return EXIT_LOAD_ERROR;
+        }
+    };
+    let result = if binary {
+        out.write_all(disasm(code, &DisasmOptions::default()).as_bytes())
+            .map_err(|e| e.to_string())
+    } else {
+        write_bytecode(code, &mut out, options.symbols).map_err(|e| e.to_string())
+    };
+    match result.and_then(|_| out.flush().map_err(|e| e.to_string())) {
+        Ok(()) => EXIT_OK,
+        Err(e) => {
+            eprintln!("Impossible d'écrire {} : {}", output, e);
+            EXIT_LOAD_ERROR
+        }
+    }
+}
 
-    println!("Transformation AppTerm :");
-    code = parser::trans_appterm(&code);
+fn run(options: &Options, code: &Program) -> i32 {
+    let mut machine = ZAM::new(code);
+    //la bibliothèque ne trace rien par défaut, l'interprète écrit sur stderr
+    let sink = match &options.trace_file {
+        Some(f) => match TraceSink::file(f) {
+            Ok(sink) => sink,
+            Err(e) => {
+                eprintln!("Impossible de créer {} : {}", f, e);
+                return EXIT_LOAD_ERROR;
+            }
+        },
+        None => TraceSink::Stderr,
+    };
+    machine.set_trace(TraceLevel::Result, sink);
+    if options.debug || options.command == Command::Trace {
+        machine.set_option("Debug");
+    }
+    if options.step {
+        machine.set_option("Step");
+    }
+    if options.gc {
+        machine.set_option("Gc");
+    }
+    machine.set_int_mode(options.ints);
+    if let Some(max) = options.max_steps {
+        machine.set_max_steps(max);
+    }
+    if let Some(max) = options.max_stack {
+        machine.set_max_stack(max);
+    }
+    if let Some(max) = options.max_heap {
+        machine.set_max_heap(max);
+    }
+    if let Some(level) = options.trace {
+        machine.set_trace_level(level);
+    }
+
+    if let Some(format) = options.profile {
+        machine.enable_profiling(format == ProfileFormat::Folded);
+    }
 
-    println!("Code de {} :", arguments[1]);
-    for inst in &code {
-        println!("{:?}", inst);
+    if options.command == Command::Debug {
+        let mut debugger = Debugger::new();
+        let stdin = io::stdin();
+        if let Err(e) = debugger.repl(&mut machine, stdin.lock(), &mut io::stdout()) {
+            eprintln!("Erreur : {}", e);
+        }
+        //quitter avant la fin n'est pas un échec
+        return match debugger.finished {
+            Some(Ok(Outcome::Exception(_))) => EXIT_EXCEPTION,
+            Some(Ok(Outcome::Value(_))) | None => EXIT_OK,
+            Some(Ok(_)) => EXIT_LIMIT,
+            Some(Err(e)) => error_status(&e.kind),
+        };
     }
+    let status = match machine.run() {
+        Ok(Outcome::Value(_)) => EXIT_OK,
+        Ok(Outcome::Exception(_)) => EXIT_EXCEPTION,
+        Ok(Outcome::StackOverflow) => {
+            eprintln!("Débordement de pile");
+            EXIT_LIMIT
+        }
+        Ok(Outcome::HeapExhausted) => {
+            eprintln!("Tas plein");
+            EXIT_LIMIT
+        }
+        Ok(Outcome::OutOfFuel) => {
+            eprintln!("Carburant épuisé");
+            EXIT_LIMIT
+        }
+        Err(e) => {
+            eprintln!("Erreur : {}", e);
+            eprint!("{}", format_backtrace(&e.backtrace));
+            error_status(&e.kind)
+        }
+    };
+    match (machine.profile(), options.profile) {
+        (Some(profile), Some(format)) => {
+            let report = profile.report(machine.prog(), format);
+            match write_report(options.output.as_deref(), &report) {
+                Ok(()) => status,
+                Err(e) => {
+                    eprintln!("Impossible d'écrire le rapport : {}", e);
+                    EXIT_LOAD_ERROR
+                }
+            }
+        }
+        _ => status,
+    }
+}
+
+//les limites données par les options ne sont pas des erreurs du programme
+fn error_status(kind: &ErrorKind) -> i32 {
+    match kind {
+        ErrorKind::StepLimit(_) | ErrorKind::HeapExhausted => EXIT_LIMIT,
+        _ => EXIT_VM_ERROR,
+    }
+}
+
+//sur la sortie d'erreur sans fichier, pour ne pas se mêler à la sortie du programme
+fn write_report(output: Option<&str>, report: &str) -> io::Result<()> {
+    match output {
+        Some(f) => std::fs::write(f, report),
+        None => io::stderr().write_all(report.as_bytes()),
+    }
+}
+
+fn main() {
+    let arguments = std::env::args().skip(1).collect::<Vec<String>>();
+    let options = parse_args(&arguments);
+    let code = load(&options);
 
-    let mut machine = machine::ZAM::new(&code);
-    machine.set_option("Debug");
-    // machine.set_option("Step");
-    machine.run();
+    let status = match options.command {
+        Command::Run | Command::Trace | Command::Debug => run(&options, &code),
+        Command::Disasm => {
+            print!("{}", disasm(&code, &options.disasm));
+            EXIT_OK
+        }
+        Command::Check => {
+            println!("{} : {} instructions", options.file, code.len());
+            EXIT_OK
+        }
+        Command::Convert => convert(&options, &code),
+    };
+    process::exit(status);
 }
@@ -1,55 +1,141 @@
+use crate::error::ErrorKind;
+use crate::heap::Heap;
+
+use std::cmp::Ordering;
+
+//étiquettes d'OCaml des valeurs qui ne sont pas des blocs de MAKEBLOCK,
+//dont l'étiquette ne dépasse pas MAX_BLOCK_TAG
+pub const MAX_BLOCK_TAG: u8 = 245;
+pub const CLOSURE_TAG: u8 = 247;
+//exceptions prédéfinies, voir primitives::exception
+pub const EXCEPTION_TAG: u8 = 248;
+pub const STRING_TAG: u8 = 252;
+pub const DOUBLE_TAG: u8 = 253;
+pub const DOUBLE_ARRAY_TAG: u8 = 254;
+
+//profondeur de blocs au-delà de laquelle compare abandonne, une valeur
+//cyclique n'est jamais égale à elle-même sans cette limite
+pub const MAX_COMPARE_DEPTH: usize = 1 << 20;
+//paramètres de Hashtbl.hash : valeurs significatives et valeurs parcourues
+const HASH_MEANINGFUL: usize = 10;
+const HASH_TOTAL: usize = 100;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Mlvalue {
     Entier(i64),
     Environement(Vec<Mlvalue>),
-    Block(Vec<Mlvalue>),
-    RefBlock(usize),
+    //indice d'un bloc du tas
+    Block(usize),
     Fermeture(usize, Vec<Mlvalue>),
+    //indice d'une chaîne d'octets du tas
+    Chaine(usize),
+    //indice d'un flottant du tas
+    Flottant(usize),
+    //indice d'un tableau de flottants du tas, dont les éléments ne sont pas
+    //des valeurs mais des f64 mis en boîte à la lecture
+    Flottants(usize),
 }
 
 impl Mlvalue {
-    pub fn as_int(&self) -> i64 {
-        println!("self {:?}", self);
+    pub fn as_int(&self) -> Result<i64, ErrorKind> {
         match self {
-            Mlvalue::Entier(n) => *n,
-            _ => panic!("Not an Entier"),
+            Mlvalue::Entier(n) => Ok(*n),
+            _ => Err(self.mismatch("Entier")),
         }
     }
-    pub fn as_bool(&self) -> bool {
+    pub fn as_bool(&self) -> Result<bool, ErrorKind> {
         match self {
-            Mlvalue::Entier(1) => true,
-            Mlvalue::Entier(0) => false,
-            _ => panic!("Not a Bool"),
+            Mlvalue::Entier(1) => Ok(true),
+            Mlvalue::Entier(0) => Ok(false),
+            _ => Err(self.mismatch("Bool")),
+        }
+    }
+    pub fn getfield(&self, n: usize, heap: &Heap) -> Result<Mlvalue, ErrorKind> {
+        let fields = heap.get(self.as_block()?)?;
+        fields.get(n).cloned().ok_or(ErrorKind::BadFieldIndex {
+            index: n as i64,
+            size: fields.len(),
+        })
+    }
+
+    pub fn setfield(&self, n: usize, val: Mlvalue, heap: &mut Heap) -> Result<(), ErrorKind> {
+        let fields = heap.get_mut(self.as_block()?)?;
+        let size = fields.len();
+        match fields.get_mut(n) {
+            Some(field) => {
+                *field = val;
+                Ok(())
+            }
+            None => Err(ErrorKind::BadFieldIndex {
+                index: n as i64,
+                size,
+            }),
         }
     }
-    pub fn getfield(&self, n: usize, stack: &Vec<Mlvalue>) -> Mlvalue {
+
+    pub fn veclen(&self, heap: &Heap) -> Result<usize, ErrorKind> {
         match self {
-            Mlvalue::Block(v) => v[n].clone(),
-            Mlvalue::RefBlock(refb) => stack[*refb].getfield(n, &stack),
-            _ => panic!("Not a block"),
+            Mlvalue::Flottants(h) => Ok(heap.get_floats(*h)?.len()),
+            _ => Ok(heap.get(self.as_block()?)?.len()),
         }
     }
 
-    pub fn setfield(&mut self, n: usize, val: Mlvalue, stack: &mut Vec<Mlvalue>) {
+    //faux pour les entiers, qui sont les constructeurs constants
+    pub fn is_block(&self) -> bool {
+        !matches!(self, Mlvalue::Entier(_))
+    }
+
+    //étiquette du constructeur d'un bloc, ou celle d'OCaml pour les
+    //fermetures, chaînes et flottants
+    pub fn tag(&self, heap: &Heap) -> Result<u8, ErrorKind> {
         match self {
-            Mlvalue::Block(v) => v[n] = val,
-            Mlvalue::RefBlock(refb) => stack[*refb].setfield(n, val, & mut Vec::default()),
-            _ => panic!("Not a block"),
+            Mlvalue::Block(h) => heap.tag(*h),
+            Mlvalue::Fermeture(_, _) => Ok(CLOSURE_TAG),
+            Mlvalue::Chaine(_) => Ok(STRING_TAG),
+            Mlvalue::Flottant(_) => Ok(DOUBLE_TAG),
+            Mlvalue::Flottants(_) => Ok(DOUBLE_ARRAY_TAG),
+            _ => Err(self.mismatch("Block")),
         }
     }
 
-    pub fn veclen(&self,stack: & Vec<Mlvalue>) -> usize {
+    //vrai pour un bloc d'étiquette tag, comme le filtrage d'un constructeur
+    pub fn has_tag(&self, tag: u8, heap: &Heap) -> bool {
+        matches!(self, Mlvalue::Block(_)) && self.tag(heap).ok() == Some(tag)
+    }
+
+    //indice du bloc dans le tas
+    pub fn as_block(&self) -> Result<usize, ErrorKind> {
+        match self {
+            Mlvalue::Block(h) => Ok(*h),
+            _ => Err(self.mismatch("Block")),
+        }
+    }
+    //indice de la chaîne dans le tas
+    pub fn as_string(&self) -> Result<usize, ErrorKind> {
+        match self {
+            Mlvalue::Chaine(h) => Ok(*h),
+            _ => Err(self.mismatch("Chaine")),
+        }
+    }
+    //valeur du flottant en boîte
+    pub fn as_float(&self, heap: &Heap) -> Result<f64, ErrorKind> {
         match self {
-            Mlvalue::Block(v) => v.len(),
-            Mlvalue::RefBlock(refb) => stack[*refb].veclen( & stack),
-            _ => panic!("Not a block"),
+            Mlvalue::Flottant(h) => heap.get_float(*h),
+            _ => Err(self.mismatch("Flottant")),
         }
     }
-    pub fn as_env(&self) -> Vec<Mlvalue> {
+    //indice du tableau de flottants dans le tas
+    pub fn as_floats(&self) -> Result<usize, ErrorKind> {
         match self {
-            Mlvalue::Entier(x) => vec![Mlvalue::Entier(*x)],
-            Mlvalue::Environement(env) => env.clone(),
-            _ => panic!("Not an Env"),
+            Mlvalue::Flottants(h) => Ok(*h),
+            _ => Err(self.mismatch("Flottants")),
+        }
+    }
+    pub fn as_env(&self) -> Result<Vec<Mlvalue>, ErrorKind> {
+        match self {
+            Mlvalue::Entier(x) => Ok(vec![Mlvalue::Entier(*x)]),
+            Mlvalue::Environement(env) => Ok(env.clone()),
+            _ => Err(self.mismatch("Env")),
         }
     }
     pub fn to_entier(value: bool) -> Mlvalue {
@@ -58,4 +144,285 @@ impl Mlvalue {
             false => Mlvalue::Entier(0),
         }
     }
+
+    //égalité physique ==, les fermetures n'étant pas dans le tas elles sont
+    //égales si elles ont le même code et des environnements physiquement égaux
+    pub fn physical_eq(&self, other: &Mlvalue) -> bool {
+        match (self, other) {
+            (Mlvalue::Entier(a), Mlvalue::Entier(b)) => a == b,
+            (Mlvalue::Block(a), Mlvalue::Block(b))
+            | (Mlvalue::Chaine(a), Mlvalue::Chaine(b))
+            | (Mlvalue::Flottant(a), Mlvalue::Flottant(b))
+            | (Mlvalue::Flottants(a), Mlvalue::Flottants(b)) => a == b,
+            (Mlvalue::Fermeture(a, env_a), Mlvalue::Fermeture(b, env_b)) => {
+                a == b && physical_eq_all(env_a, env_b)
+            }
+            (Mlvalue::Environement(a), Mlvalue::Environement(b)) => physical_eq_all(a, b),
+            _ => false,
+        }
+    }
+
+    //comparaison structurelle d'OCaml : les entiers avant les blocs, puis
+    //les blocs par étiquette, taille et champs de gauche à droite.
+    //Avec total, c'est compare : nan est égal à lui-même et plus petit que
+    //les autres flottants, et un bloc est égal à lui-même sans être parcouru.
+    //Sinon, c'est =, < ... : None quand un nan rend les valeurs incomparables
+    pub fn compare(
+        &self,
+        other: &Mlvalue,
+        heap: &Heap,
+        total: bool,
+    ) -> Result<Option<Ordering>, ErrorKind> {
+        //paires restant à comparer et leur profondeur
+        let mut todo = vec![(self.clone(), other.clone(), 0)];
+        while let Some((a, b, depth)) = todo.pop() {
+            if depth > MAX_COMPARE_DEPTH {
+                return Err(ErrorKind::BadOperand(format!(
+                    "compare : profondeur {} dépassée",
+                    MAX_COMPARE_DEPTH
+                )));
+            }
+            let order = match (&a, &b) {
+                (Mlvalue::Entier(x), Mlvalue::Entier(y)) => x.cmp(y),
+                (Mlvalue::Entier(_), _) => Ordering::Less,
+                (_, Mlvalue::Entier(_)) => Ordering::Greater,
+                _ if total && a.physical_eq(&b) => Ordering::Equal,
+                //une fermeture ne se compare à aucun bloc, quel que soit son tag
+                (Mlvalue::Fermeture(..), _) | (_, Mlvalue::Fermeture(..)) => {
+                    return Err(ErrorKind::BadOperand(
+                        "compare : valeur fonctionnelle".to_string(),
+                    ))
+                }
+                _ => {
+                    let (ta, tb) = (a.tag(heap)?, b.tag(heap)?);
+                    if ta != tb {
+                        return Ok(Some(ta.cmp(&tb)));
+                    }
+                    match (&a, &b) {
+                        (Mlvalue::Chaine(x), Mlvalue::Chaine(y)) => {
+                            heap.get_string(*x)?.cmp(heap.get_string(*y)?)
+                        }
+                        (Mlvalue::Flottant(_), Mlvalue::Flottant(_)) => {
+                            match compare_floats(a.as_float(heap)?, b.as_float(heap)?, total) {
+                                Some(order) => order,
+                                None => return Ok(None),
+                            }
+                        }
+                        (Mlvalue::Flottants(x), Mlvalue::Flottants(y)) => {
+                            let (x, y) = (heap.get_floats(*x)?, heap.get_floats(*y)?);
+                            let mut order = x.len().cmp(&y.len());
+                            for (x, y) in x.iter().zip(y) {
+                                if order != Ordering::Equal {
+                                    break;
+                                }
+                                match compare_floats(*x, *y, total) {
+                                    Some(o) => order = o,
+                                    None => return Ok(None),
+                                }
+                            }
+                            order
+                        }
+                        (Mlvalue::Block(x), Mlvalue::Block(y)) => {
+                            let (x, y) = (heap.get(*x)?, heap.get(*y)?);
+                            if x.len() != y.len() {
+                                return Ok(Some(x.len().cmp(&y.len())));
+                            }
+                            //le premier champ est comparé en premier
+                            for (x, y) in x.iter().zip(y).rev() {
+                                todo.push((x.clone(), y.clone(), depth + 1));
+                            }
+                            Ordering::Equal
+                        }
+                        _ => {
+                            return Err(ErrorKind::BadOperand(
+                                "compare : valeur fonctionnelle".to_string(),
+                            ))
+                        }
+                    }
+                }
+            };
+            if order != Ordering::Equal {
+                return Ok(Some(order));
+            }
+        }
+        Ok(Some(Ordering::Equal))
+    }
+
+    //Hashtbl.hash d'OCaml 4 (MurmurHash3), parcours en largeur d'au plus
+    //HASH_TOTAL valeurs dont HASH_MEANINGFUL entiers, chaînes ou flottants
+    pub fn hash(&self, heap: &Heap) -> Result<i64, ErrorKind> {
+        let mut h: u32 = 0;
+        let mut queue = vec![self.clone()];
+        let mut read = 0;
+        let mut meaningful = HASH_MEANINGFUL;
+        while read < queue.len() && meaningful > 0 {
+            let v = queue[read].clone();
+            read += 1;
+            //les champs mis en file après l'en-tête, sans dépasser HASH_TOTAL
+            let fields = match &v {
+                Mlvalue::Entier(n) => {
+                    h = hash_mix_intnat(h, n.wrapping_mul(2).wrapping_add(1));
+                    meaningful -= 1;
+                    continue;
+                }
+                Mlvalue::Chaine(s) => {
+                    h = hash_mix_string(h, heap.get_string(*s)?);
+                    meaningful -= 1;
+                    continue;
+                }
+                Mlvalue::Flottant(_) => {
+                    h = hash_mix_double(h, v.as_float(heap)?);
+                    meaningful -= 1;
+                    continue;
+                }
+                Mlvalue::Flottants(floats) => {
+                    for x in heap.get_floats(*floats)? {
+                        h = hash_mix_double(h, *x);
+                        meaningful -= 1;
+                        if meaningful == 0 {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                Mlvalue::Block(b) => {
+                    let fields = heap.get(*b)?;
+                    h = hash_mix(h, header(fields.len(), heap.tag(*b)?));
+                    fields.clone()
+                }
+                //le code d'une fermeture compte, son environnement est parcouru
+                Mlvalue::Fermeture(pc, env) => {
+                    h = hash_mix(h, header(env.len() + 1, CLOSURE_TAG));
+                    h = hash_mix_intnat(h, *pc as i64);
+                    meaningful -= 1;
+                    env.clone()
+                }
+                Mlvalue::Environement(env) => {
+                    h = hash_mix(h, header(env.len(), 0));
+                    env.clone()
+                }
+            };
+            for field in fields {
+                if queue.len() >= HASH_TOTAL {
+                    break;
+                }
+                queue.push(field);
+            }
+        }
+        h ^= h >> 16;
+        h = h.wrapping_mul(0x85eb_ca6b);
+        h ^= h >> 13;
+        h = h.wrapping_mul(0xc2b2_ae35);
+        h ^= h >> 16;
+        Ok((h & 0x3fff_ffff) as i64)
+    }
+
+    fn mismatch(&self, expected: &'static str) -> ErrorKind {
+        ErrorKind::TypeMismatch {
+            expected,
+            found: self.clone(),
+        }
+    }
+}
+
+//écriture d'OCaml : string_of_float x est "%.12g" suivi d'un point quand le
+//résultat ressemble à un entier
+pub fn string_of_float(x: f64) -> String {
+    if x.is_nan() {
+        return "nan".to_string();
+    }
+    if x.is_infinite() {
+        return if x > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    //exposant décimal après arrondi à 12 chiffres significatifs
+    let scientific = format!("{:.11e}", x);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let mut text = if (-4..12).contains(&exponent) {
+        trim_zeros(format!("{:.*}", (11 - exponent) as usize, x))
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!(
+            "{}e{}{:02}",
+            trim_zeros(mantissa.to_string()),
+            sign,
+            exponent.abs()
+        )
+    };
+    if text.chars().all(|c| c.is_ascii_digit() || c == '-') {
+        text.push('.');
+    }
+    text
+}
+
+//zéros inutiles après la virgule, et la virgule elle-même
+fn trim_zeros(text: String) -> String {
+    if !text.contains('.') {
+        return text;
+    }
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn physical_eq_all(a: &[Mlvalue], b: &[Mlvalue]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.physical_eq(b))
+}
+
+fn compare_floats(x: f64, y: f64, total: bool) -> Option<Ordering> {
+    match x.partial_cmp(&y) {
+        Some(order) => Some(order),
+        None if !total => None,
+        None => Some(x.is_nan().cmp(&y.is_nan()).reverse()),
+    }
+}
+
+//en-tête d'un bloc sans les bits de couleur du GC
+fn header(size: usize, tag: u8) -> u32 {
+    ((size as u32) << 10) | tag as u32
+}
+
+fn hash_mix(h: u32, d: u32) -> u32 {
+    let d = d
+        .wrapping_mul(0xcc9e_2d51)
+        .rotate_left(15)
+        .wrapping_mul(0x1b87_3593);
+    (h ^ d)
+        .rotate_left(13)
+        .wrapping_mul(5)
+        .wrapping_add(0xe654_6b64)
+}
+
+//les entiers de 32 bits hachent pareil sur 32 et 64 bits
+fn hash_mix_intnat(h: u32, d: i64) -> u32 {
+    hash_mix(h, ((d >> 32) ^ (d >> 63) ^ d) as u32)
+}
+
+fn hash_mix_double(h: u32, x: f64) -> u32 {
+    let bits = x.to_bits();
+    let (mut high, mut low) = ((bits >> 32) as u32, bits as u32);
+    if x.is_nan() {
+        high = 0x7ff0_0000;
+        low = 1;
+    } else if x == 0.0 {
+        high = 0;
+    }
+    hash_mix(hash_mix(h, low), high)
+}
+
+fn hash_mix_string(mut h: u32, s: &[u8]) -> u32 {
+    let mut chunks = s.chunks_exact(4);
+    for chunk in &mut chunks {
+        h = hash_mix(
+            h,
+            u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]),
+        );
+    }
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        let w = rest
+            .iter()
+            .enumerate()
+            .fold(0, |w, (i, b)| w | (*b as u32) << (8 * i));
+        h = hash_mix(h, w);
+    }
+    h ^ s.len() as u32
 }
@@ -0,0 +1,223 @@
+//optimisations à lucarne sur le bytecode ; les labels sont préservés,
+//ceux d'une instruction supprimée passent à l'instruction suivante
+use std::collections::{HashMap, HashSet};
+
+use crate::machine::Inst;
+use crate::Program;
+
+pub const PASS_NAMES: [&str; 4] = ["appterm", "push-pop", "jumps", "dead-code"];
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Passes {
+    //APPLY n ; RETURN k devient APPTERM n,n+k
+    pub appterm: bool,
+    //PUSH ; POP ne fait rien
+    pub push_pop: bool,
+    //un saut vers un BRANCH va directement à sa cible,
+    //un BRANCH vers l'instruction suivante disparaît
+    pub jumps: bool,
+    //code inatteignable après BRANCH, SWITCH, STOP, RETURN, APPTERM ou RAISE
+    pub dead_code: bool,
+}
+
+impl Default for Passes {
+    fn default() -> Passes {
+        Passes {
+            appterm: true,
+            push_pop: true,
+            jumps: true,
+            dead_code: true,
+        }
+    }
+}
+
+impl Passes {
+    pub fn none() -> Passes {
+        Passes {
+            appterm: false,
+            push_pop: false,
+            jumps: false,
+            dead_code: false,
+        }
+    }
+
+    //active ou désactive une passe d'après son nom de PASS_NAMES,
+    //renvoie faux si le nom est inconnu
+    pub fn set(&mut self, name: &str, enabled: bool) -> bool {
+        let pass = match name {
+            "appterm" => &mut self.appterm,
+            "push-pop" => &mut self.push_pop,
+            "jumps" => &mut self.jumps,
+            "dead-code" => &mut self.dead_code,
+            _ => return false,
+        };
+        *pass = enabled;
+        true
+    }
+}
+
+//une instruction peut porter plusieurs labels pendant l'optimisation
+struct Item {
+    labels: Vec<String>,
+    inst: Inst,
+}
+
+pub fn optimize(code: &[(Option<String>, Inst)], passes: &Passes) -> Program {
+    let mut items: Vec<Item> = code
+        .iter()
+        .map(|(l, inst)| Item {
+            labels: l.iter().cloned().collect(),
+            inst: inst.clone(),
+        })
+        .collect();
+
+    if passes.appterm {
+        appterm(&mut items);
+    }
+    if passes.push_pop {
+        push_pop(&mut items);
+    }
+    if passes.jumps {
+        jumps(&mut items);
+    }
+    if passes.dead_code {
+        dead_code(&mut items);
+    }
+    finish(items)
+}
+
+//supprime l'instruction i, ses labels désignent désormais la suivante
+fn remove(items: &mut Vec<Item>, i: usize) {
+    let item = items.remove(i);
+    if let Some(next) = items.get_mut(i) {
+        let mut labels = item.labels;
+        labels.append(&mut next.labels);
+        next.labels = labels;
+    }
+}
+
+fn appterm(items: &mut Vec<Item>) {
+    let mut i = 0;
+    while i + 1 < items.len() {
+        if let (Inst::Apply(n), Inst::Return(k)) = (&items[i].inst, &items[i + 1].inst) {
+            items[i].inst = Inst::AppTerm(*n, *n + *k);
+            //un RETURN désigné par un label reste atteignable par un saut
+            if items[i + 1].labels.is_empty() {
+                items.remove(i + 1);
+            }
+        }
+        i += 1;
+    }
+}
+
+fn push_pop(items: &mut Vec<Item>) {
+    let mut i = 0;
+    while i + 1 < items.len() {
+        let removable = items[i].inst == Inst::Push
+            && items[i + 1].inst == Inst::Pop
+            && items[i + 1].labels.is_empty()
+            && (i + 2 < items.len() || items[i].labels.is_empty());
+        if removable {
+            remove(items, i + 1);
+            remove(items, i);
+            //PUSH ; PUSH ; POP ; POP se simplifie en deux fois
+            i = i.saturating_sub(1);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+fn jumps(items: &mut Vec<Item>) {
+    let mut position: HashMap<String, usize> = HashMap::new();
+    for (i, item) in items.iter().enumerate() {
+        for l in &item.labels {
+            position.insert(l.clone(), i);
+        }
+    }
+
+    //cible finale d'une chaîne de BRANCH, les boucles sont laissées telles quelles
+    let follow = |l: &String| {
+        let mut target = l.clone();
+        let mut seen = HashSet::new();
+        while let Some(&j) = position.get(&target) {
+            match &items[j].inst {
+                Inst::Branch(next) if seen.insert(j) => target = next.clone(),
+                _ => break,
+            }
+        }
+        target
+    };
+    let threaded: Vec<Vec<String>> = items
+        .iter()
+        .map(|item| match &item.inst {
+            Inst::Branch(_) | Inst::BranchIfNot(_) | Inst::Switch(_, _) => {
+                item.inst.targets().into_iter().map(follow).collect()
+            }
+            _ => Vec::new(),
+        })
+        .collect();
+    for (item, targets) in items.iter_mut().zip(threaded) {
+        for (l, target) in item.inst.targets_mut().into_iter().zip(targets) {
+            *l = target;
+        }
+    }
+
+    let mut i = 0;
+    while i + 1 < items.len() {
+        match &items[i].inst {
+            Inst::Branch(l) if items[i + 1].labels.contains(l) => {
+                remove(items, i);
+                //le BRANCH précédent peut maintenant viser l'instruction suivante
+                i = i.saturating_sub(1);
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+fn dead_code(items: &mut Vec<Item>) {
+    let referenced: HashSet<String> = items
+        .iter()
+        .flat_map(|item| item.inst.targets())
+        .cloned()
+        .collect();
+
+    let mut live = true;
+    items.retain(|item| {
+        //RESTART est atteint par une fermeture partielle créée au GRAB suivant
+        if item.inst == Inst::Restart || item.labels.iter().any(|l| referenced.contains(l)) {
+            live = true;
+        }
+        let keep = live;
+        if live && item.inst.is_terminator() {
+            live = false;
+        }
+        keep
+    });
+}
+
+//garde le premier label de chaque instruction, les autres deviennent des alias
+fn finish(items: Vec<Item>) -> Program {
+    let mut aliases: HashMap<String, String> = HashMap::new();
+    let mut prog: Program = Vec::with_capacity(items.len());
+    for item in items {
+        let mut labels = item.labels.into_iter();
+        let first = labels.next();
+        if let Some(first) = &first {
+            for alias in labels {
+                aliases.insert(alias, first.clone());
+            }
+        }
+        prog.push((first, item.inst));
+    }
+
+    for (_, inst) in prog.iter_mut() {
+        for l in inst.targets_mut() {
+            if let Some(alias) = aliases.get(l) {
+                *l = alias.clone();
+            }
+        }
+    }
+    prog
+}
@@ -1,100 +1,389 @@
+use std::fmt;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::path::Path;
+use std::str::FromStr;
 
 use crate::machine::Inst;
+use crate::optimizer::{self, Passes};
 
-pub fn reader(filename: &str) -> BufReader<File> {
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    //texte fautif, vide en fin de ligne
+    pub text: String,
+    pub expected: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let found = if self.text.is_empty() {
+            "fin de ligne".to_string()
+        } else {
+            format!("`{}`", self.text)
+        };
+        write!(
+            f,
+            "{}:{}:{}: {} trouvé, {} attendu",
+            self.file, self.line, self.column, found, self.expected
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub fn reader(filename: &str) -> io::Result<BufReader<File>> {
     let path = Path::new(filename);
-    let file = File::open(&path).expect("Impossible to open file.");
-    BufReader::new(file)
+    let file = File::open(path)?;
+    Ok(BufReader::new(file))
 }
 
-pub fn parse_prog(fichier: BufReader<File>) -> Vec<(Option<String>, Inst)> {
-    let mut prog: Vec<(Option<String>, Inst)> = Vec::new();
-    for l in fichier.lines() {
-        let line: String = l.unwrap();
-        let mut tokens = line.split_whitespace().collect::<Vec<&str>>();
-        let mut label = None;
-
-        if !line.starts_with("\t") {
-            let mut value: String = tokens[0].to_string();
-            value.pop();
-            label = Some(value);
-            tokens.remove(0);
-        }
-
-        let instr: Inst;
-        match tokens[0] {
-            "CONST" => instr = Inst::Const(tokens[1].parse::<i64>().unwrap()),
-            "PRIM" => instr = Inst::Prim(tokens[1].to_string()),
-            "BRANCH" => instr = Inst::Branch(tokens[1].to_string()),
-            "BRANCHIFNOT" => instr = Inst::BranchIfNot(tokens[1].to_string()),
-            "PUSH" => instr = Inst::Push,
-            "POP" => instr = Inst::Pop,
-            "ACC" => instr = Inst::Acc(tokens[1].parse::<u64>().unwrap()),
-            "ENVACC" => instr = Inst::Envacc(tokens[1].parse::<u64>().unwrap()),
-            "CLOSURE" => {
-                let args = tokens[1].split(',').collect::<Vec<&str>>();
-                instr = Inst::Closure(args[0].to_string(), args[1].parse::<i64>().unwrap())
+//un mot de la ligne et sa colonne (à partir de 1)
+type Token<'a> = (usize, &'a str);
+
+//les mots sont séparés par des blancs ; une chaîne entre guillemets reste
+//dans un seul mot et un `;` hors d'une chaîne commence un commentaire.
+//Renvoie aussi la colonne de la fin du code
+fn tokenize(line: &str) -> (Vec<Token<'_>>, usize) {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (col, (i, c)) in line.char_indices().enumerate() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
             }
-            "APPLY" => instr = Inst::Apply(tokens[1].parse::<i64>().unwrap()),
-            "RETURN" => instr = Inst::Return(tokens[1].parse::<i64>().unwrap()),
-            "STOP" => instr = Inst::Stop,
-            "CLOSUREREC" => {
-                let args = tokens[1].split(',').collect::<Vec<&str>>();
-                instr = Inst::ClosureRec(args[0].to_string(), args[1].parse::<i64>().unwrap())
+            continue;
+        }
+        if c == ';' {
+            if let Some((scol, si)) = start {
+                tokens.push((scol, &line[si..i]));
             }
-            "OFFSETCLOSURE" => instr = Inst::OffSetClosure,
-            "GRAB" => instr = Inst::Grab(tokens[1].parse::<i64>().unwrap()),
-            "RESTART" => instr = Inst::Restart,
-            "APPTERM" => {
-                let args = tokens[1].split(',').collect::<Vec<&str>>();
-                instr = Inst::AppTerm(
-                    args[0].parse::<i64>().unwrap(),
-                    args[1].parse::<i64>().unwrap(),
-                )
+            return (tokens, col + 1);
+        }
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some((col + 1, i)),
+            (true, Some((scol, si))) => {
+                tokens.push((scol, &line[si..i]));
+                start = None;
             }
-            "MAKEBLOCK" => instr = Inst::Makeblock(tokens[1].parse::<i64>().unwrap()),
-            "GETFIELD" => instr = Inst::Getfield(tokens[1].parse::<i64>().unwrap()),
-            "VECTLENGTH" => instr = Inst::Veclength,
-            "GETVECTITEM" => instr = Inst::Getvectitem,
-            "SETFIELD" => instr = Inst::Setfield(tokens[1].parse::<i64>().unwrap()),
-            "SETVECTITEM" => instr = Inst::SetVectitem,
-            "ASSIGN" => instr = Inst::Assign(tokens[1].parse::<i64>().unwrap()),
-            _ => panic!("instruction non supportée"),
+            _ => {}
         }
-        prog.push((label, instr));
+        in_string = c == '"';
     }
+    if let Some((scol, si)) = start {
+        tokens.push((scol, &line[si..]));
+    }
+    (tokens, line.chars().count() + 1)
+}
 
-    prog
+//chaîne entre guillemets dans la syntaxe de CONSTSTRING
+pub fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\{:03}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
 }
 
-pub fn trans_appterm(code: &Vec<(Option<String>, Inst)>) -> Vec<(Option<String>, Inst)> {
-    let mut prog: Vec<(Option<String>, Inst)> = Vec::with_capacity(code.len());
-
-    let mut i = 0;
-    while i < code.len() {
-        match &code[i] {
-            (_l1, Inst::Apply(n)) => {
-                if i + 1 < code.len() {
-                    match &code[i + 1] {
-                        (_l2, Inst::Return(k)) => {
-                            //on ignore les labels pour l'instant
-                            prog.push((None, Inst::AppTerm(*n, *k + *n)));
-                            i += 1;
+//inverse de quote, text est un mot commençant par un guillemet
+fn unquote(col: usize, text: &str) -> Result<String, LineError> {
+    let expected = "une chaîne entre guillemets";
+    let inner = match text.strip_prefix('"') {
+        Some(inner) => inner,
+        None => return Err((col, text.to_string(), expected)),
+    };
+    let mut s = String::new();
+    let mut chars = inner.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' if i + 1 == inner.len() => return Ok(s),
+            '"' => break,
+            '\\' => {
+                let escape_col = col + inner[..i].chars().count() + 1;
+                let bad = |len: usize| {
+                    let text = inner[i..].chars().take(len).collect();
+                    (escape_col, text, "\\\\, \\\", \\n, \\t, \\r ou \\ddd")
+                };
+                match chars.next().map(|(_, c)| c) {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some(d) if d.is_ascii_digit() => {
+                        let mut code = d.to_digit(10).unwrap();
+                        for _ in 0..2 {
+                            match chars.next() {
+                                Some((_, d)) if d.is_ascii_digit() => {
+                                    code = code * 10 + d.to_digit(10).unwrap()
+                                }
+                                _ => return Err(bad(4)),
+                            }
+                        }
+                        //les chaînes du format texte sont en UTF-8
+                        if code > 127 {
+                            return Err(bad(4));
                         }
-                        _ => prog.push(code[i].clone()),
+                        s.push(code as u8 as char);
                     }
-                } else {
-                    prog.push(code[i].clone());
+                    _ => return Err(bad(2)),
                 }
             }
-            _ => prog.push(code[i].clone()),
+            c => s.push(c),
+        }
+    }
+    Err((col, text.to_string(), expected))
+}
+
+//analyse d'une ligne, les erreurs ne portent que la colonne et le texte fautif
+struct Line<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    end: usize,
+}
+
+type LineError = (usize, String, &'static str);
+
+impl<'a> Line<'a> {
+    fn next(&mut self, expected: &'static str) -> Result<Token<'a>, LineError> {
+        match self.tokens.get(self.pos) {
+            Some(tok) => {
+                self.pos += 1;
+                Ok(*tok)
+            }
+            None => Err((self.end, String::new(), expected)),
+        }
+    }
+
+    fn number<T: FromStr>(&mut self, expected: &'static str) -> Result<T, LineError> {
+        let (col, text) = self.next(expected)?;
+        text.parse::<T>()
+            .map_err(|_| (col, text.to_string(), expected))
+    }
+
+    fn string(&mut self) -> Result<String, LineError> {
+        let (col, text) = self.next("une chaîne entre guillemets")?;
+        unquote(col, text)
+    }
+
+    fn label(&mut self) -> Result<String, LineError> {
+        Ok(self.next("un label")?.1.to_string())
+    }
+
+    //liste [l1,l2,...], éventuellement vide
+    fn label_list(&mut self) -> Result<Vec<String>, LineError> {
+        let expected = "une liste de labels [l1,l2,...]";
+        let (col, text) = self.next(expected)?;
+        let inner = text.strip_prefix('[').and_then(|t| t.strip_suffix(']'));
+        match inner {
+            Some("") => Ok(Vec::new()),
+            Some(inner) if inner.split(',').all(|l| !l.is_empty()) => {
+                Ok(inner.split(',').map(|l| l.to_string()).collect())
+            }
+            _ => Err((col, text.to_string(), expected)),
+        }
+    }
+
+    //argument de la forme label,n
+    fn label_and_number(&mut self) -> Result<(String, i64), LineError> {
+        let (col, text) = self.next("label,entier")?;
+        match text.split_once(',') {
+            Some((l, n)) if !l.is_empty() => match n.parse::<i64>() {
+                Ok(n) => Ok((l.to_string(), n)),
+                Err(_) => Err((col + l.chars().count() + 1, n.to_string(), "un entier")),
+            },
+            _ => Err((col, text.to_string(), "label,entier")),
+        }
+    }
+
+    //argument de la forme n,m
+    fn two_numbers(&mut self) -> Result<(i64, i64), LineError> {
+        let (col, text) = self.next("entier,entier")?;
+        let parts = text.split_once(',');
+        match parts.map(|(n, m)| (n.parse::<i64>(), m.parse::<i64>())) {
+            Some((Ok(n), Ok(m))) => Ok((n, m)),
+            _ => Err((col, text.to_string(), "entier,entier")),
+        }
+    }
+
+    //argument de la forme n ou n,étiquette, l'étiquette valant 0 par défaut
+    fn size_and_tag(&mut self) -> Result<(i64, i64), LineError> {
+        let (col, text) = self.next("un entier")?;
+        match text.split_once(',') {
+            None => text
+                .parse::<i64>()
+                .map(|n| (n, 0))
+                .map_err(|_| (col, text.to_string(), "un entier")),
+            Some((n, tag)) => match (n.parse::<i64>(), tag.parse::<i64>()) {
+                (Ok(n), Ok(tag)) => Ok((n, tag)),
+                _ => Err((col, text.to_string(), "entier,étiquette")),
+            },
+        }
+    }
+
+    //seule valeur acceptée pour un argument facultatif
+    fn optional_operand(&mut self, value: &'static str) -> Result<(), LineError> {
+        match self.tokens.get(self.pos) {
+            Some((col, text)) if *text != value => Err((*col, text.to_string(), value)),
+            Some(_) => {
+                self.pos += 1;
+                Ok(())
+            }
+            None => Ok(()),
         }
-        i += 1;
     }
 
-    prog
+    fn finish(&mut self) -> Result<(), LineError> {
+        match self.tokens.get(self.pos) {
+            None => Ok(()),
+            Some((col, text)) => Err((*col, text.to_string(), "fin de ligne")),
+        }
+    }
+}
+
+fn parse_line(line: &str) -> Result<Option<(Option<String>, Inst)>, LineError> {
+    let (tokens, end) = tokenize(line);
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+    let mut line = Line {
+        tokens,
+        pos: 0,
+        end,
+    };
+
+    let mut label = None;
+    let (col, first) = line.tokens[0];
+    if let Some(l) = first.strip_suffix(':') {
+        if l.is_empty() {
+            return Err((col, first.to_string(), "un label"));
+        }
+        label = Some(l.to_string());
+        line.pos += 1;
+    }
+
+    let (col, mnemonic) = line.next("une instruction")?;
+    let instr = match mnemonic {
+        "CONST" => Inst::Const(line.number("un entier")?),
+        "CONSTSTRING" => Inst::ConstString(line.string()?),
+        "CONSTFLOAT" => Inst::ConstFloat(line.number("un flottant")?),
+        "PRIM" => Inst::Prim(line.next("une primitive")?.1.to_string()),
+        "BRANCH" => Inst::Branch(line.label()?),
+        "BRANCHIFNOT" => Inst::BranchIfNot(line.label()?),
+        "PUSH" => Inst::Push,
+        //ocamlc écrit parfois POP 1 et OFFSETCLOSURE 0
+        "POP" => {
+            line.optional_operand("1")?;
+            Inst::Pop
+        }
+        "ACC" => Inst::Acc(line.number("un entier positif")?),
+        "ENVACC" => Inst::Envacc(line.number("un entier positif")?),
+        "CLOSURE" => {
+            let (l, n) = line.label_and_number()?;
+            Inst::Closure(l, n)
+        }
+        "APPLY" => Inst::Apply(line.number("un entier")?),
+        "RETURN" => Inst::Return(line.number("un entier")?),
+        "STOP" => Inst::Stop,
+        "CLOSUREREC" => {
+            let (l, n) = line.label_and_number()?;
+            Inst::ClosureRec(l, n)
+        }
+        "OFFSETCLOSURE" => {
+            line.optional_operand("0")?;
+            Inst::OffSetClosure
+        }
+        "GRAB" => Inst::Grab(line.number("un entier")?),
+        "RESTART" => Inst::Restart,
+        "APPTERM" => {
+            let (n, m) = line.two_numbers()?;
+            Inst::AppTerm(n, m)
+        }
+        "MAKEBLOCK" => {
+            let (n, tag) = line.size_and_tag()?;
+            Inst::Makeblock(n, tag)
+        }
+        "GETTAG" => Inst::GetTag,
+        "SWITCH" => {
+            let consts = line.label_list()?;
+            Inst::Switch(consts.into(), line.label_list()?.into())
+        }
+        "GETFIELD" => Inst::Getfield(line.number("un entier")?),
+        "VECTLENGTH" => Inst::Veclength,
+        "GETVECTITEM" => Inst::Getvectitem,
+        "SETFIELD" => Inst::Setfield(line.number("un entier")?),
+        "SETVECTITEM" => Inst::SetVectitem,
+        "ASSIGN" => Inst::Assign(line.number("un entier")?),
+        "PUSHTRAP" => Inst::PushTrap(line.label()?),
+        "POPTRAP" => Inst::PopTrap,
+        "RAISE" => Inst::Raise,
+        _ => return Err((col, mnemonic.to_string(), "une instruction")),
+    };
+    line.finish()?;
+
+    Ok(Some((label, instr)))
+}
+
+//source sert uniquement à nommer le fichier dans les erreurs
+pub fn parse_prog<R: BufRead>(
+    fichier: R,
+    source: &str,
+) -> Result<Vec<(Option<String>, Inst)>, Vec<ParseError>> {
+    let mut prog: Vec<(Option<String>, Inst)> = Vec::new();
+    let mut errors = Vec::new();
+
+    for (i, l) in fichier.lines().enumerate() {
+        let error = |(column, text, expected): LineError| ParseError {
+            file: source.to_string(),
+            line: i + 1,
+            column,
+            text,
+            expected,
+        };
+        let line = match l {
+            Ok(line) => line,
+            Err(_) => {
+                errors.push(error((1, String::new(), "une ligne en UTF-8")));
+                continue;
+            }
+        };
+        match parse_line(&line) {
+            Ok(Some(inst)) => prog.push(inst),
+            Ok(None) => {}
+            Err(e) => errors.push(error(e)),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(prog)
+    } else {
+        Err(errors)
+    }
+}
+
+//fusion APPLY ; RETURN seule, voir optimizer::optimize pour les autres passes
+pub fn trans_appterm(code: &[(Option<String>, Inst)]) -> Vec<(Option<String>, Inst)> {
+    let passes = Passes {
+        appterm: true,
+        ..Passes::none()
+    };
+    optimizer::optimize(code, &passes)
 }
@@ -0,0 +1,556 @@
+//table des primitives appelées par PRIM : chaque nom reçoit un indice,
+//les PRIM du programme sont résolus une fois au chargement (voir linker::primitives)
+use crate::error::ErrorKind;
+use crate::heap::Heap;
+use crate::mlvalue::{self, Mlvalue};
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::Write;
+
+//exceptions prédéfinies, chacune lue par la primitive sans argument du
+//même nom ; les exceptions déclarées par le programme restent des entiers
+//numérotés à partir de 0
+pub const PREDEFINED_EXCEPTIONS: [&str; 3] = ["Match_failure", "Division_by_zero", "Failure"];
+
+//arithmétique des primitives entières, les débordements reviennent
+//toujours à l'autre extrémité
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IntMode {
+    //entiers Rust de 64 bits
+    #[default]
+    Native,
+    //entiers OCaml de 63 bits
+    OCaml,
+}
+
+impl IntMode {
+    pub fn from_name(name: &str) -> Option<IntMode> {
+        match name {
+            "native" => Some(IntMode::Native),
+            "ocaml" => Some(IntMode::OCaml),
+            _ => None,
+        }
+    }
+
+    pub fn bits(self) -> u32 {
+        match self {
+            IntMode::Native => 64,
+            IntMode::OCaml => 63,
+        }
+    }
+
+    //ramène un résultat calculé sur 64 bits à la taille des entiers
+    //en recopiant le bit de signe
+    pub fn wrap(self, n: i64) -> i64 {
+        match self {
+            IntMode::Native => n,
+            IntMode::OCaml => (n << 1) >> 1,
+        }
+    }
+
+    //nombre de positions d'un décalage, None hors de 0..bits
+    fn shift(self, n: i64) -> Option<u32> {
+        if (0..self.bits() as i64).contains(&n) {
+            Some(n as u32)
+        } else {
+            None
+        }
+    }
+}
+
+//ce qu'une primitive peut modifier dans la machine
+pub struct Context<'a> {
+    pub heap: &'a mut Heap,
+    //sortie du programme
+    pub output: &'a mut dyn Write,
+    pub ints: IntMode,
+}
+
+//reçoit l'accu puis les arguments dépilés, renvoie le nouvel accu
+pub type Native = Box<dyn FnMut(&[Mlvalue], &mut Context) -> Result<Mlvalue, ErrorKind>>;
+
+pub struct Primitive {
+    pub name: String,
+    //nombre d'arguments, accu compris : PRIM dépile arity - 1 valeurs
+    pub arity: usize,
+    native: Native,
+}
+
+pub struct Primitives {
+    table: Vec<Primitive>,
+    ids: HashMap<String, usize>,
+}
+
+impl Default for Primitives {
+    fn default() -> Primitives {
+        Primitives::new()
+    }
+}
+
+impl Primitives {
+    //table contenant les primitives prédéfinies
+    pub fn new() -> Primitives {
+        let mut primitives = Primitives::empty();
+        primitives.register("+", 2, int(i64::wrapping_add));
+        primitives.register("-", 2, int(i64::wrapping_sub));
+        primitives.register("*", 2, int(i64::wrapping_mul));
+        //quotient arrondi vers zéro, min_int / -1 vaut min_int
+        primitives.register("/", 2, division(i64::wrapping_div));
+        primitives.register("mod", 2, division(i64::wrapping_rem));
+        primitives.register("~-", 1, int1(i64::wrapping_neg));
+        primitives.register("abs", 1, int1(i64::wrapping_abs));
+        primitives.register("min", 2, int(i64::min));
+        primitives.register("max", 2, int(i64::max));
+        primitives.register("land", 2, int(|a, b| a & b));
+        primitives.register("lor", 2, int(|a, b| a | b));
+        primitives.register("lxor", 2, int(|a, b| a ^ b));
+        //décalages hors de 0..bits : 0 pour lsl et lsr, le signe pour asr
+        primitives.register("lsl", 2, shift(|a, n, _| n.map_or(0, |n| a << n)));
+        primitives.register(
+            "lsr",
+            2,
+            shift(|a, n, ints| {
+                let mask = u64::MAX >> (64 - ints.bits());
+                n.map_or(0, |n| ((a as u64 & mask) >> n) as i64)
+            }),
+        );
+        primitives.register(
+            "asr",
+            2,
+            shift(|a, n, ints| a >> n.unwrap_or(ints.bits() - 1)),
+        );
+        //comparaisons structurelles, fausses pour des valeurs incomparables
+        //à cause d'un nan sauf <>
+        primitives.register("<", 2, compare(|o| o == Some(Ordering::Less)));
+        primitives.register(">", 2, compare(|o| o == Some(Ordering::Greater)));
+        primitives.register("=", 2, compare(|o| o == Some(Ordering::Equal)));
+        primitives.register("<>", 2, compare(|o| o != Some(Ordering::Equal)));
+        primitives.register(
+            "<=",
+            2,
+            compare(|o| matches!(o, Some(Ordering::Less) | Some(Ordering::Equal))),
+        );
+        primitives.register(
+            ">=",
+            2,
+            compare(|o| matches!(o, Some(Ordering::Greater) | Some(Ordering::Equal))),
+        );
+        //-1, 0 ou 1, ordre total où nan est le plus petit des flottants
+        primitives.register(
+            "compare",
+            2,
+            Box::new(|args, context| {
+                let order = args[0].compare(&args[1], context.heap, true)?;
+                Ok(Mlvalue::Entier(order.map_or(0, |o| o as i64)))
+            }),
+        );
+        primitives.register(
+            "==",
+            2,
+            Box::new(|args, _| Ok(Mlvalue::to_entier(args[0].physical_eq(&args[1])))),
+        );
+        primitives.register(
+            "!=",
+            2,
+            Box::new(|args, _| Ok(Mlvalue::to_entier(!args[0].physical_eq(&args[1])))),
+        );
+        primitives.register(
+            "hash",
+            1,
+            Box::new(|args, context| Ok(Mlvalue::Entier(args[0].hash(context.heap)?))),
+        );
+        primitives.register(
+            "not",
+            1,
+            Box::new(|args, _| Ok(Mlvalue::to_entier(!args[0].as_bool()?))),
+        );
+        primitives.register(
+            "or",
+            2,
+            Box::new(|args, _| Ok(Mlvalue::to_entier(args[0].as_bool()? || args[1].as_bool()?))),
+        );
+        primitives.register(
+            "and",
+            2,
+            Box::new(|args, _| Ok(Mlvalue::to_entier(args[0].as_bool()? && args[1].as_bool()?))),
+        );
+        primitives.register(
+            "print",
+            1,
+            Box::new(|args, context| {
+                let val = args[0].as_int()?;
+                //une erreur d'écriture ne doit pas arrêter le programme
+                let _ = write!(context.output, "{}", (val as u8) as char);
+                Ok(args[0].clone())
+            }),
+        );
+        primitives.register_strings();
+        primitives.register_floats();
+        for name in PREDEFINED_EXCEPTIONS.iter() {
+            primitives.register(
+                name,
+                0,
+                Box::new(move |_, context| Ok(exception(context.heap, name))),
+            );
+        }
+        primitives
+    }
+
+    //flottants en boîte, chaque résultat est un nouveau flottant du tas
+    fn register_floats(&mut self) {
+        self.register("+.", 2, float(|a, b| a + b));
+        self.register("-.", 2, float(|a, b| a - b));
+        self.register("*.", 2, float(|a, b| a * b));
+        self.register("/.", 2, float(|a, b| a / b));
+        self.register("**", 2, float(f64::powf));
+        self.register("~-.", 1, float1(|x| -x));
+        self.register("sqrt", 1, float1(f64::sqrt));
+        self.register("exp", 1, float1(f64::exp));
+        self.register("log", 1, float1(f64::ln));
+        self.register("sin", 1, float1(f64::sin));
+        self.register("cos", 1, float1(f64::cos));
+        //nan n'est égal à rien, pas même à lui-même
+        self.register("=.", 2, float_compare(|a, b| a == b));
+        self.register("<>.", 2, float_compare(|a, b| a != b));
+        self.register("<.", 2, float_compare(|a, b| a < b));
+        self.register(">.", 2, float_compare(|a, b| a > b));
+        self.register("<=.", 2, float_compare(|a, b| a <= b));
+        self.register(">=.", 2, float_compare(|a, b| a >= b));
+        self.register(
+            "float_of_int",
+            1,
+            Box::new(|args, context| Ok(context.heap.alloc_float(args[0].as_int()? as f64))),
+        );
+        //tronqué vers zéro
+        self.register(
+            "int_of_float",
+            1,
+            Box::new(|args, context| {
+                let x = args[0].as_float(context.heap)?;
+                Ok(Mlvalue::Entier(context.ints.wrap(x as i64)))
+            }),
+        );
+        self.register(
+            "string_of_float",
+            1,
+            Box::new(|args, context| {
+                let s = mlvalue::string_of_float(args[0].as_float(context.heap)?);
+                Ok(context.heap.alloc_string(s.into_bytes()))
+            }),
+        );
+        self.register(
+            "print_float",
+            1,
+            Box::new(|args, context| {
+                let s = mlvalue::string_of_float(args[0].as_float(context.heap)?);
+                let _ = write!(context.output, "{}", s);
+                Ok(Mlvalue::Entier(0))
+            }),
+        );
+        //tableau de args[0] fois le flottant args[1], lu et modifié par
+        //GETVECTITEM, SETVECTITEM et VECTLENGTH
+        self.register(
+            "float_array_make",
+            2,
+            Box::new(|args, context| {
+                let n = args[0].as_int()?;
+                if n < 0 {
+                    return Err(ErrorKind::BadOperand("taille négative".to_string()));
+                }
+                let x = args[1].as_float(context.heap)?;
+                context.heap.reserve(Heap::floats_words(n as usize))?;
+                let mut floats = buffer(n as usize)?;
+                floats.resize(n as usize, x);
+                Ok(context.heap.alloc_floats(floats))
+            }),
+        );
+    }
+
+    //chaînes d'octets, un caractère est représenté par son code
+    fn register_strings(&mut self) {
+        self.register(
+            "string_length",
+            1,
+            Box::new(|args, context| {
+                let s = context.heap.get_string(args[0].as_string()?)?;
+                Ok(Mlvalue::Entier(s.len() as i64))
+            }),
+        );
+        self.register(
+            "string_get",
+            2,
+            Box::new(|args, context| {
+                let s = context.heap.get_string(args[0].as_string()?)?;
+                let i = index(args[1].as_int()?, s.len())?;
+                Ok(Mlvalue::Entier(s[i] as i64))
+            }),
+        );
+        self.register(
+            "string_set",
+            3,
+            Box::new(|args, context| {
+                let c = args[2].as_int()?;
+                let s = context.heap.get_string_mut(args[0].as_string()?)?;
+                let i = index(args[1].as_int()?, s.len())?;
+                s[i] = c as u8;
+                Ok(Mlvalue::Entier(0))
+            }),
+        );
+        self.register(
+            "^",
+            2,
+            Box::new(|args, context| {
+                let a = context.heap.get_string(args[0].as_string()?)?;
+                let b = context.heap.get_string(args[1].as_string()?)?;
+                context
+                    .heap
+                    .reserve(Heap::string_words(a.len() + b.len()))?;
+                let mut s = buffer(a.len() + b.len())?;
+                s.extend_from_slice(a);
+                s.extend_from_slice(b);
+                Ok(context.heap.alloc_string(s))
+            }),
+        );
+        //sous-chaîne de la position args[1] de longueur args[2]
+        self.register(
+            "string_sub",
+            3,
+            Box::new(|args, context| {
+                let s = context.heap.get_string(args[0].as_string()?)?;
+                let (start, len) = (args[1].as_int()?, args[2].as_int()?);
+                if start < 0 || len < 0 || len > s.len() as i64 - start {
+                    return Err(ErrorKind::BadOperand(format!(
+                        "sous-chaîne {} de longueur {} d'une chaîne de taille {}",
+                        start,
+                        len,
+                        s.len()
+                    )));
+                }
+                context.heap.reserve(Heap::string_words(len as usize))?;
+                let sub = s[start as usize..(start + len) as usize].to_vec();
+                Ok(context.heap.alloc_string(sub))
+            }),
+        );
+        //-1, 0 ou 1 selon l'ordre lexicographique des octets
+        self.register(
+            "string_compare",
+            2,
+            Box::new(|args, context| {
+                let a = context.heap.get_string(args[0].as_string()?)?;
+                let b = context.heap.get_string(args[1].as_string()?)?;
+                Ok(Mlvalue::Entier(a.cmp(b) as i64))
+            }),
+        );
+        self.register(
+            "print_string",
+            1,
+            Box::new(|args, context| {
+                let s = context.heap.get_string(args[0].as_string()?)?;
+                let _ = context.output.write_all(s);
+                Ok(Mlvalue::Entier(0))
+            }),
+        );
+        self.register(
+            "string_of_int",
+            1,
+            Box::new(|args, context| {
+                let s = args[0].as_int()?.to_string();
+                Ok(context.heap.alloc_string(s.into_bytes()))
+            }),
+        );
+        self.register(
+            "int_of_string",
+            1,
+            Box::new(|args, context| {
+                let s = context.heap.get_string(args[0].as_string()?)?;
+                match int_of_string(s, context.ints) {
+                    Some(n) => Ok(Mlvalue::Entier(n)),
+                    None => Err(ErrorKind::Raise(exception(context.heap, "Failure"))),
+                }
+            }),
+        );
+    }
+
+    pub fn empty() -> Primitives {
+        Primitives {
+            table: Vec::new(),
+            ids: HashMap::new(),
+        }
+    }
+
+    //une primitive déjà définie est remplacée et garde son indice
+    pub fn register(&mut self, name: &str, arity: usize, native: Native) -> usize {
+        let primitive = Primitive {
+            name: name.to_string(),
+            arity,
+            native,
+        };
+        match self.ids.get(name) {
+            Some(&id) => {
+                self.table[id] = primitive;
+                id
+            }
+            None => {
+                self.table.push(primitive);
+                self.ids.insert(name.to_string(), self.table.len() - 1);
+                self.table.len() - 1
+            }
+        }
+    }
+
+    pub fn id(&self, name: &str) -> Option<usize> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Primitive> {
+        self.table.get(id)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.table.iter().map(|p| p.name.as_str())
+    }
+
+    //args contient exactement arity valeurs
+    pub fn call(
+        &mut self,
+        id: usize,
+        args: &[Mlvalue],
+        context: &mut Context,
+    ) -> Result<Mlvalue, ErrorKind> {
+        match self.table.get_mut(id) {
+            Some(primitive) => (primitive.native)(args, context),
+            None => Err(ErrorKind::UnknownPrimitive(id.to_string())),
+        }
+    }
+}
+
+//exception prédéfinie : un bloc d'étiquette EXCEPTION_TAG contenant son
+//nom, qu'aucun MAKEBLOCK ni aucun entier levé par RAISE ne peut imiter
+pub fn exception(heap: &mut Heap, name: &str) -> Mlvalue {
+    let name = heap.alloc_string(name.as_bytes().to_vec());
+    heap.alloc_tagged(mlvalue::EXCEPTION_TAG, vec![name])
+}
+
+fn index(i: i64, size: usize) -> Result<usize, ErrorKind> {
+    if i < 0 || i as usize >= size {
+        Err(ErrorKind::BadFieldIndex { index: i, size })
+    } else {
+        Ok(i as usize)
+    }
+}
+
+//tampon de n éléments, tas plein plutôt qu'un abandon si l'hôte refuse
+fn buffer<T>(n: usize) -> Result<Vec<T>, ErrorKind> {
+    let mut v = Vec::new();
+    v.try_reserve_exact(n)
+        .map_err(|_| ErrorKind::HeapExhausted)?;
+    Ok(v)
+}
+
+//syntaxe d'OCaml : signe, préfixe 0x, 0o ou 0b, chiffres séparés par des _ ;
+//en hexadécimal, octal et binaire les entiers vont jusqu'à 2^bits - 1 et
+//reviennent aux négatifs au-delà du plus grand entier
+fn int_of_string(s: &[u8], ints: IntMode) -> Option<i64> {
+    let s = std::str::from_utf8(s).ok()?;
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let prefix = s.get(..2).map(|p| p.to_ascii_lowercase());
+    let (radix, digits) = match prefix.as_deref() {
+        Some("0x") => (16, &s[2..]),
+        Some("0o") => (8, &s[2..]),
+        Some("0b") => (2, &s[2..]),
+        _ => (10, s),
+    };
+    if !digits.chars().next()?.is_digit(radix) {
+        return None;
+    }
+    let mut magnitude: u128 = 0;
+    for c in digits.chars().filter(|c| *c != '_') {
+        magnitude = magnitude * radix as u128 + c.to_digit(radix)? as u128;
+        if magnitude > 1 << 64 {
+            return None;
+        }
+    }
+    let limit = if radix == 10 {
+        (1u128 << (ints.bits() - 1)) - !negative as u128
+    } else {
+        (1u128 << ints.bits()) - 1
+    };
+    if magnitude > limit {
+        return None;
+    }
+    let n = ints.wrap(magnitude as u64 as i64);
+    Some(if negative {
+        ints.wrap(n.wrapping_neg())
+    } else {
+        n
+    })
+}
+
+fn int1(f: fn(i64) -> i64) -> Native {
+    Box::new(move |args, context| Ok(Mlvalue::Entier(context.ints.wrap(f(args[0].as_int()?)))))
+}
+
+fn division(f: fn(i64, i64) -> i64) -> Native {
+    Box::new(move |args, context| match args[1].as_int()? {
+        0 => Err(ErrorKind::DivisionByZero),
+        b => Ok(Mlvalue::Entier(context.ints.wrap(f(args[0].as_int()?, b)))),
+    })
+}
+
+fn int(f: fn(i64, i64) -> i64) -> Native {
+    Box::new(move |args, context| {
+        let n = f(args[0].as_int()?, args[1].as_int()?);
+        Ok(Mlvalue::Entier(context.ints.wrap(n)))
+    })
+}
+
+//f reçoit le nombre de positions validé par IntMode::shift
+fn shift(f: fn(i64, Option<u32>, IntMode) -> i64) -> Native {
+    Box::new(move |args, context| {
+        let n = context.ints.shift(args[1].as_int()?);
+        let result = f(args[0].as_int()?, n, context.ints);
+        Ok(Mlvalue::Entier(context.ints.wrap(result)))
+    })
+}
+
+fn float(f: fn(f64, f64) -> f64) -> Native {
+    Box::new(move |args, context| {
+        let x = f(
+            args[0].as_float(context.heap)?,
+            args[1].as_float(context.heap)?,
+        );
+        Ok(context.heap.alloc_float(x))
+    })
+}
+
+fn float1(f: fn(f64) -> f64) -> Native {
+    Box::new(move |args, context| {
+        let x = f(args[0].as_float(context.heap)?);
+        Ok(context.heap.alloc_float(x))
+    })
+}
+
+fn float_compare(f: fn(f64, f64) -> bool) -> Native {
+    Box::new(move |args, context| {
+        let (a, b) = (
+            args[0].as_float(context.heap)?,
+            args[1].as_float(context.heap)?,
+        );
+        Ok(Mlvalue::to_entier(f(a, b)))
+    })
+}
+
+fn compare(f: fn(Option<Ordering>) -> bool) -> Native {
+    Box::new(move |args, context| {
+        let order = match (&args[0], &args[1]) {
+            //cas le plus fréquent, sans parcours
+            (Mlvalue::Entier(a), Mlvalue::Entier(b)) => Some(a.cmp(b)),
+            (a, b) => a.compare(b, context.heap, false)?,
+        };
+        Ok(Mlvalue::to_entier(f(order)))
+    })
+}
@@ -0,0 +1,244 @@
+//profilage d'une exécution : nombre d'instructions exécutées par sorte
+//d'instruction, par pc et par fonction, et nombre d'appels de chaque fonction
+use crate::machine::Inst;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+//nom donné au code qui n'appartient à aucune fermeture
+pub const MAIN: &str = "<main>";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProfileFormat {
+    //tableaux lisibles
+    Table,
+    Json,
+    //une ligne "main;f;g n" par pile d'appels, lue par flamegraph.pl ou inferno
+    Folded,
+}
+
+impl ProfileFormat {
+    pub fn from_name(name: &str) -> Option<ProfileFormat> {
+        match name {
+            "table" => Some(ProfileFormat::Table),
+            "json" => Some(ProfileFormat::Json),
+            "folded" => Some(ProfileFormat::Folded),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Profile {
+    //fonction contenant chaque pc
+    functions: Vec<String>,
+    pub total: u64,
+    pub by_pc: Vec<u64>,
+    pub by_inst: BTreeMap<&'static str, u64>,
+    pub by_function: BTreeMap<String, u64>,
+    //appels par APPLY ou APPTERM
+    pub calls: BTreeMap<String, u64>,
+    //instructions par pile d'appels repliée, gardées seulement si demandé
+    //car la pile est reparcourue à chaque instruction
+    pub stacks: Option<BTreeMap<String, u64>>,
+}
+
+impl Profile {
+    pub fn new(prog: &[(Option<String>, Inst)], code: &[Inst<usize>], stacks: bool) -> Profile {
+        Profile {
+            functions: functions(prog, code),
+            total: 0,
+            by_pc: vec![0; code.len()],
+            by_inst: BTreeMap::new(),
+            by_function: BTreeMap::new(),
+            calls: BTreeMap::new(),
+            stacks: if stacks { Some(BTreeMap::new()) } else { None },
+        }
+    }
+
+    //fonction contenant le pc, MAIN hors du programme
+    pub fn function(&self, pc: usize) -> &str {
+        self.functions.get(pc).map(|f| f.as_str()).unwrap_or(MAIN)
+    }
+
+    //callee est le pc de la fermeture appelée par APPLY ou APPTERM,
+    //chain les pcs des activations, la plus récente en premier
+    pub fn record(
+        &mut self,
+        pc: usize,
+        inst: &Inst<usize>,
+        callee: Option<usize>,
+        chain: &[usize],
+    ) {
+        self.total += 1;
+        if let Some(count) = self.by_pc.get_mut(pc) {
+            *count += 1;
+        }
+        *self.by_inst.entry(inst.mnemonic()).or_insert(0) += 1;
+        let function = self.function(pc).to_string();
+        *self.by_function.entry(function).or_insert(0) += 1;
+        if let Some(callee) = callee {
+            let callee = self.function(callee).to_string();
+            *self.calls.entry(callee).or_insert(0) += 1;
+        }
+
+        if self.stacks.is_some() {
+            let mut names = vec![MAIN];
+            for pc in chain.iter().rev() {
+                let name = self.function(*pc);
+                //l'activation principale est toujours à la base
+                if name != MAIN || names.len() > 1 {
+                    names.push(name);
+                }
+            }
+            let stack = names.join(";");
+            if let Some(stacks) = &mut self.stacks {
+                *stacks.entry(stack).or_insert(0) += 1;
+            }
+        }
+    }
+
+    pub fn report(&self, prog: &[(Option<String>, Inst)], format: ProfileFormat) -> String {
+        match format {
+            ProfileFormat::Table => self.table(prog),
+            ProfileFormat::Json => self.json(),
+            ProfileFormat::Folded => self.folded(),
+        }
+    }
+
+    fn table(&self, prog: &[(Option<String>, Inst)]) -> String {
+        let mut text = String::new();
+        let percent = |n: u64| 100.0 * n as f64 / self.total.max(1) as f64;
+        let _ = writeln!(text, "{} instructions exécutées", self.total);
+
+        let _ = writeln!(text, "\n{:<14}{:>12}{:>8}", "instruction", "nombre", "%");
+        for (name, n) in sorted(self.by_inst.iter().map(|(k, v)| (k.to_string(), *v))) {
+            let _ = writeln!(text, "{:<14}{:>12}{:>7.1}%", name, n, percent(n));
+        }
+
+        let _ = writeln!(
+            text,
+            "\n{:<14}{:>12}{:>8}{:>10}",
+            "fonction", "nombre", "%", "appels"
+        );
+        for (name, n) in sorted(self.by_function.iter().map(|(k, v)| (k.clone(), *v))) {
+            let calls = self.calls.get(&name).copied().unwrap_or(0);
+            let _ = writeln!(
+                text,
+                "{:<14}{:>12}{:>7.1}%{:>10}",
+                name,
+                n,
+                percent(n),
+                calls
+            );
+        }
+
+        let _ = writeln!(text, "\n{:<6}{:>12}{:>8}  instruction", "pc", "nombre", "%");
+        let hot = self.by_pc.iter().enumerate().filter(|(_, n)| **n > 0);
+        for (pc, n) in sorted(hot.map(|(pc, n)| (pc, *n))).into_iter().take(10) {
+            let inst = prog.get(pc).map(|(_, i)| i.to_string()).unwrap_or_default();
+            let _ = writeln!(text, "{:<6}{:>12}{:>7.1}%  {}", pc, n, percent(n), inst);
+        }
+        text
+    }
+
+    fn json(&self) -> String {
+        let instructions: Vec<String> = self
+            .by_inst
+            .iter()
+            .map(|(name, n)| format!("{}:{}", quote(name), n))
+            .collect();
+        let pcs: Vec<String> = self.by_pc.iter().map(|n| n.to_string()).collect();
+        let functions: Vec<String> = self
+            .by_function
+            .iter()
+            .map(|(name, n)| {
+                let calls = self.calls.get(name).copied().unwrap_or(0);
+                format!(
+                    "{}:{{\"instructions\":{},\"calls\":{}}}",
+                    quote(name),
+                    n,
+                    calls
+                )
+            })
+            .collect();
+        format!(
+            "{{\"total\":{},\"instructions\":{{{}}},\"pcs\":[{}],\"functions\":{{{}}}}}\n",
+            self.total,
+            instructions.join(","),
+            pcs.join(","),
+            functions.join(",")
+        )
+    }
+
+    fn folded(&self) -> String {
+        let mut text = String::new();
+        for (stack, n) in self.stacks.iter().flatten() {
+            let _ = writeln!(text, "{} {}", stack, n);
+        }
+        text
+    }
+}
+
+//décroissant par nombre, puis par clé
+fn sorted<K: Ord>(counts: impl Iterator<Item = (K, u64)>) -> Vec<(K, u64)> {
+    let mut counts: Vec<(K, u64)> = counts.collect();
+    counts.sort_by(|(ka, a), (kb, b)| b.cmp(a).then(ka.cmp(kb)));
+    counts
+}
+
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+//le corps d'une fonction est le code atteint depuis son point d'entrée,
+//cible d'un CLOSURE ou d'un CLOSUREREC, sans passer par un appel ;
+//le code principal est celui atteint depuis le pc 0
+fn functions(prog: &[(Option<String>, Inst)], code: &[Inst<usize>]) -> Vec<String> {
+    let mut owner: Vec<Option<String>> = vec![None; code.len()];
+    let mut entries = vec![(0, MAIN.to_string())];
+    for inst in code {
+        if let Inst::Closure(entry, _) | Inst::ClosureRec(entry, _) = inst {
+            let name = match prog.get(*entry) {
+                Some((Some(l), _)) => l.clone(),
+                _ => format!("L{}", entry),
+            };
+            entries.push((*entry, name));
+        }
+    }
+
+    for (entry, name) in entries {
+        //le RESTART précédant le GRAB d'entrée, visé par les fermetures partielles
+        let mut todo = vec![entry];
+        if entry > 0 && code.get(entry - 1) == Some(&Inst::Restart) {
+            todo.push(entry - 1);
+        }
+        while let Some(pc) = todo.pop() {
+            match owner.get_mut(pc) {
+                Some(o @ None) => *o = Some(name.clone()),
+                _ => continue,
+            }
+            if !code[pc].is_terminator() {
+                todo.push(pc + 1);
+            }
+            match &code[pc] {
+                Inst::Closure(_, _) | Inst::ClosureRec(_, _) => {}
+                inst => todo.extend(inst.targets().into_iter().copied()),
+            }
+        }
+    }
+    owner
+        .into_iter()
+        .map(|o| o.unwrap_or_else(|| MAIN.to_string()))
+        .collect()
+}
@@ -0,0 +1,116 @@
+//diagnostics de l'interprète, séparés de la sortie du programme
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+//chaque niveau inclut les précédents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TraceLevel {
+    Off,
+    //issue du programme et statistiques de fin
+    Result,
+    //chaque instruction exécutée
+    Inst,
+    //état complet de la machine avant chaque instruction
+    State,
+}
+
+impl TraceLevel {
+    pub fn from_name(name: &str) -> Option<TraceLevel> {
+        match name {
+            "off" => Some(TraceLevel::Off),
+            "result" => Some(TraceLevel::Result),
+            "inst" => Some(TraceLevel::Inst),
+            "state" => Some(TraceLevel::State),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TraceLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TraceLevel::Off => "off",
+            TraceLevel::Result => "result",
+            TraceLevel::Inst => "inst",
+            TraceLevel::State => "state",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//reçoit chaque ligne de trace avec son niveau
+pub type TraceCallback = Box<dyn FnMut(TraceLevel, &str)>;
+
+pub enum TraceSink {
+    Stderr,
+    //fichier ou tout autre flux
+    Writer(Box<dyn Write>),
+    //lignes gardées en mémoire, lues avec Tracer::lines
+    Buffer(Vec<String>),
+    Callback(TraceCallback),
+    //lignes ignorées
+    Null,
+}
+
+impl TraceSink {
+    pub fn file<P: AsRef<Path>>(path: P) -> io::Result<TraceSink> {
+        Ok(TraceSink::Writer(Box::new(BufWriter::new(File::create(
+            path,
+        )?))))
+    }
+}
+
+pub struct Tracer {
+    pub level: TraceLevel,
+    pub sink: TraceSink,
+}
+
+impl Default for Tracer {
+    fn default() -> Tracer {
+        //une machine embarquée n'écrit rien tant que l'hôte ne choisit pas de
+        //destination, voir ZAM::set_trace
+        Tracer::new(TraceLevel::Result, TraceSink::Null)
+    }
+}
+
+impl Tracer {
+    pub fn new(level: TraceLevel, sink: TraceSink) -> Tracer {
+        Tracer { level, sink }
+    }
+
+    pub fn enabled(&self, level: TraceLevel) -> bool {
+        level != TraceLevel::Off && level <= self.level
+    }
+
+    pub fn emit(&mut self, level: TraceLevel, message: &str) {
+        if !self.enabled(level) {
+            return;
+        }
+        //une erreur d'écriture ne doit pas arrêter le programme
+        match &mut self.sink {
+            TraceSink::Stderr => eprintln!("{}", message),
+            TraceSink::Writer(w) => {
+                let _ = writeln!(w, "{}", message);
+            }
+            TraceSink::Buffer(lines) => lines.push(message.to_string()),
+            TraceSink::Callback(f) => f(level, message),
+            TraceSink::Null => {}
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let TraceSink::Writer(w) = &mut self.sink {
+            let _ = w.flush();
+        }
+    }
+
+    //lignes reçues par un TraceSink::Buffer, vide pour les autres
+    pub fn lines(&self) -> &[String] {
+        match &self.sink {
+            TraceSink::Buffer(lines) => lines,
+            _ => &[],
+        }
+    }
+}
@@ -0,0 +1,9 @@
+let div x y =
+  try x / y with
+  | Division_by_zero -> -1
+
+let rem x y =
+  try x mod y with
+  | Division_by_zero -> -2
+
+let _ = div 7 0 + rem 7 0 + div 7 2
@@ -0,0 +1,3 @@
+let f x = 10 / x
+
+let _ = f 0
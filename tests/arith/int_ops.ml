@@ -0,0 +1,4 @@
+let _ =
+  (17 mod 5, (-17) mod 5, 17 mod (-5), - (3 + 4), abs (-12),
+   12 land 10, 12 lor 10, 12 lxor 10, 1 lsl 10, 64 lsr 2, (-16) asr 2,
+   3 <> 4, 3 <> 3)
@@ -0,0 +1,83 @@
+use mini_zam::error::format_backtrace;
+use mini_zam::{compile_str, ErrorKind, Inst, TraceLevel, TraceSink, VmError, ZAM};
+
+mod common;
+
+#[test]
+fn error_deep_in_recursion() {
+    let mut machine =
+        common::compiled("let rec f n = if n = 0 then fst 3 else 1 + f (n - 1) in\nf 3");
+    let err = machine.run().unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::TypeMismatch { .. }));
+
+    //l'instruction fautive, trois appels récursifs puis l'appel principal
+    let pcs: Vec<usize> = err.backtrace.iter().map(|(pc, _)| *pc).collect();
+    assert_eq!(pcs[0], err.pc);
+    assert_eq!(pcs.len(), 5);
+    assert!(pcs[1..4].iter().all(|pc| *pc == pcs[1]));
+    assert_eq!(err.backtrace[0].1, "L1+6");
+    assert!(format_backtrace(&err.backtrace).starts_with("  #0 pc 13 (L1+6)\n  #1 pc 21 (L2+6)\n"));
+}
+
+#[test]
+fn uncaught_exception_is_traced_with_backtrace() {
+    let code =
+        compile_str("exception E\nlet rec f n = if n = 0 then raise E else 1 + f (n - 1) in\nf 2")
+            .unwrap();
+    let mut machine = ZAM::new(&code);
+    machine.set_trace(TraceLevel::Result, TraceSink::Buffer(Vec::new()));
+    machine.run().unwrap();
+    let lines: Vec<&str> = machine.tracer().lines()[0].lines().collect();
+    assert!(lines[0].starts_with("Exception non rattrapée"));
+    //RAISE, deux appels récursifs, l'appel principal
+    assert_eq!(lines.len(), 5);
+    assert!(lines[1].starts_with("  #0 pc "));
+}
+
+fn run_source(source: &str) -> VmError {
+    common::run(source).unwrap_err()
+}
+
+#[test]
+fn apply_of_non_closure_pushes_no_frame() {
+    let err = run_source("\tCONST 1\n\tPUSH\n\tCONST 2\n\tAPPLY 1\n\tSTOP\n");
+    assert!(matches!(err.kind, ErrorKind::TypeMismatch { .. }));
+    assert_eq!(err.backtrace.len(), 1);
+}
+
+#[test]
+fn application_without_arguments_is_rejected() {
+    let err = run_source("\tCONST 1\n\tAPPLY 0\n\tSTOP\n");
+    assert!(matches!(err.kind, ErrorKind::BadOperand(_)));
+    let err = run_source("\tCONST 1\n\tAPPTERM 0,1\n\tSTOP\n");
+    assert!(matches!(err.kind, ErrorKind::BadOperand(_)));
+}
+
+#[test]
+fn makeblock_larger_than_stack_underflows() {
+    let err = run_source("\tCONST 1\n\tMAKEBLOCK 1000000000000\n\tSTOP\n");
+    assert!(matches!(err.kind, ErrorKind::StackUnderflow));
+}
+
+#[test]
+fn grab_at_the_first_instruction_is_an_error() {
+    //un argument dans la pile, puis retour au pc 0 sans RESTART devant
+    let mut machine = common::machine("\tSTOP\n");
+    machine.execute(&Inst::Push).unwrap();
+    machine.execute(&Inst::Branch(0)).unwrap();
+    let err = machine.execute(&Inst::Grab(1)).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::PcOutOfRange);
+}
+
+#[test]
+fn raise_checks_the_trap_frame_is_on_the_stack() {
+    //le cadre de PUSHTRAP est dépilé à la main, sous lui le cadre de l'APPLY
+    //ressemble à un cadre de rattrapage valide
+    let mut machine = common::machine(
+        "\tCONST 0\n\tPUSH\n\tCONST 1\n\tPUSH\n\tCLOSURE L1,0\n\tAPPLY 1\n\tSTOP\n\
+         L1:\tPOP\n\tPUSHTRAP L2\n\tPOP\n\tPOP\n\tPOP\n\tPOP\n\tRAISE\nL2:\tSTOP\n",
+    );
+    machine.set_max_steps(1000);
+    let err = machine.run().unwrap_err();
+    assert_eq!(err.kind, ErrorKind::StackUnderflow);
+}
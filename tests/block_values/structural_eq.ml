@@ -0,0 +1,15 @@
+(* égalité structurelle sur les listes, n-uplets et chaînes *)
+let check b = print_char (if b then 'o' else 'n')
+
+let l = [1; 2; 3]
+let () = check (l = [1; 2; 3])
+let () = check (l <> [1; 2])
+let () = check ([1; 2] < l)
+let () = check ((1, "b") > (1, "a"))
+let () = check ("abc" = "ab" ^ "c")
+let () = check (l == l)
+let () = check ((1, 2) != (1, 2))
+let () = check (Hashtbl.hash [1; 2] = Hashtbl.hash [1; 2])
+let () = print_char '\n'
+
+let _ = Hashtbl.hash 1
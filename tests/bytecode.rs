@@ -0,0 +1,135 @@
+use mini_zam::{load_str, read_bytecode, write_bytecode, BytecodeError, Inst, Outcome, ZAM};
+
+const PROG: &str = "\tBRANCH L2
+L1:\tACC 0
+\tPUSH
+\tCONST -300
+\tPRIM +
+\tRETURN 1
+L2:\tCLOSURE L1,0
+\tPUSH
+\tCONST 1000000
+\tPUSH
+\tACC 1
+\tAPPLY 1
+\tSTOP
+";
+
+fn encode(symbols: bool) -> Vec<u8> {
+    let code = load_str(PROG, "prog").unwrap();
+    let mut bytes = Vec::new();
+    write_bytecode(&code, &mut bytes, symbols).unwrap();
+    bytes
+}
+
+#[test]
+fn round_trip_with_symbols() {
+    let code = load_str(PROG, "prog").unwrap();
+    let read = read_bytecode(&mut encode(true).as_slice()).unwrap();
+    assert_eq!(read, code);
+}
+
+#[test]
+fn stripped_labels_are_synthesized() {
+    let read = read_bytecode(&mut encode(false).as_slice()).unwrap();
+    assert_eq!(read[0], (None, Inst::Branch(".L6".to_string())));
+    assert_eq!(read[1].0, Some(".L1".to_string()));
+    assert_eq!(
+        read[6],
+        (Some(".L6".to_string()), Inst::Closure(".L1".to_string(), 0))
+    );
+    assert_eq!(
+        ZAM::new(&read).run().unwrap(),
+        Outcome::Value(mini_zam::Mlvalue::Entier(999_700))
+    );
+}
+
+#[test]
+fn bad_header() {
+    let mut bytes = encode(true);
+    bytes[0] = b'X';
+    assert!(matches!(
+        read_bytecode(&mut bytes.as_slice()),
+        Err(BytecodeError::BadMagic)
+    ));
+
+    let mut bytes = encode(true);
+    bytes[4] = 9;
+    assert!(matches!(
+        read_bytecode(&mut bytes.as_slice()),
+        Err(BytecodeError::UnsupportedVersion(9))
+    ));
+}
+
+#[test]
+fn truncated_file() {
+    let bytes = encode(true);
+    for len in [3, 10, bytes.len() - 1] {
+        assert!(matches!(
+            read_bytecode(&mut &bytes[..len]),
+            Err(BytecodeError::Io(_))
+        ));
+    }
+}
+
+#[test]
+fn undefined_label_is_not_written() {
+    let code = vec![(None, Inst::Branch("absent".to_string()))];
+    assert!(matches!(
+        write_bytecode(&code, &mut Vec::new(), true),
+        Err(BytecodeError::Link(_))
+    ));
+}
+
+#[test]
+fn synthesized_labels_avoid_the_symbol_section() {
+    //la section ne nomme que l'instruction 0, et l'appelle comme la cible 1
+    let mut bytes = encode(false);
+    bytes[6] = 1;
+    bytes.extend_from_slice(&[2, 0, 2, b'L', b'1', 2, 3, b'.', b'L', b'6']);
+    let read = read_bytecode(&mut bytes.as_slice()).unwrap();
+    assert_eq!(
+        read[0],
+        (Some("L1".to_string()), Inst::Branch("..L6".to_string()))
+    );
+    assert_eq!(read[1].0, Some(".L1".to_string()));
+    assert_eq!(read[2].0, Some(".L6".to_string()));
+}
+
+fn constant(n: i64) -> Vec<u8> {
+    let code = vec![(None, Inst::Const(n)), (None, Inst::Stop)];
+    let mut bytes = Vec::new();
+    write_bytecode(&code, &mut bytes, false).unwrap();
+    bytes
+}
+
+//remplace l'argument de CONST, codé sur un octet, par un entier LEB128
+fn with_operand(leb: &[u8]) -> Result<Vec<(Option<String>, Inst)>, BytecodeError> {
+    let mut bytes = constant(0);
+    let stop = bytes.pop().unwrap();
+    bytes.pop();
+    bytes.extend_from_slice(leb);
+    bytes.push(stop);
+    read_bytecode(&mut bytes.as_slice())
+}
+
+#[test]
+fn integers_use_the_full_64_bits() {
+    for n in [i64::MIN, i64::MAX, -1] {
+        let read = read_bytecode(&mut constant(n).as_slice()).unwrap();
+        assert_eq!(read[0].1, Inst::Const(n));
+    }
+}
+
+#[test]
+fn oversized_integers_are_rejected() {
+    let mut too_long = vec![0x80; 10];
+    too_long.push(0);
+    let mut overflow = vec![0x80; 9];
+    overflow.push(0x02);
+    let mut bad_sign = vec![0xff; 9];
+    bad_sign.push(0x3f);
+    for leb in [too_long, overflow, bad_sign] {
+        assert!(matches!(with_operand(&leb), Err(BytecodeError::BadInteger)));
+    }
+}
@@ -0,0 +1,40 @@
+use std::path::Path;
+use std::process::Command;
+
+//code de sortie de Mini-ZAM lancé sur un programme de tests/
+fn status(args: &[&str], file: &str) -> i32 {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(file);
+    Command::new(env!("CARGO_BIN_EXE_Mini-ZAM"))
+        .args(args)
+        .arg(path)
+        .output()
+        .unwrap()
+        .status
+        .code()
+        .unwrap()
+}
+
+#[test]
+fn exit_codes() {
+    assert_eq!(status(&["run"], "rec_funs/facto.txt"), 0);
+    assert_eq!(status(&["run"], "exceptions/exn_uncaught.txt"), 1);
+    assert_eq!(status(&["run", "--bad-flag"], "rec_funs/facto.txt"), 64);
+}
+
+#[test]
+fn limits_exit_with_the_limit_code() {
+    assert_eq!(
+        status(&["run", "--max-steps", "10"], "rec_funs/facto.txt"),
+        4
+    );
+    assert_eq!(
+        status(&["run", "--max-stack", "2"], "rec_funs/facto.txt"),
+        4
+    );
+    assert_eq!(
+        status(&["run", "--max-heap", "2"], "block_values/liste_length.txt"),
+        4
+    );
+}
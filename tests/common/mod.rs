@@ -0,0 +1,47 @@
+//machines sans diagnostics partagées par les tests d'intégration, chaque
+//fichier n'en utilise qu'une partie
+#![allow(dead_code)]
+
+use mini_zam::mlvalue::EXCEPTION_TAG;
+use mini_zam::{compile_str, load_file, load_str, Mlvalue, Outcome, Program, VmError, ZAM};
+
+use std::path::Path;
+
+//le traceur par défaut n'écrit rien
+pub fn quiet(code: &Program) -> ZAM {
+    ZAM::new(code)
+}
+
+//programme au format texte
+pub fn machine(source: &str) -> ZAM {
+    quiet(&load_str(source, "prog").unwrap())
+}
+
+//programme mini-ML
+pub fn compiled(source: &str) -> ZAM {
+    quiet(&compile_str(source).unwrap())
+}
+
+//fichier relatif au dossier tests
+pub fn file(name: &str) -> ZAM {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(name);
+    quiet(&load_file(path).unwrap())
+}
+
+pub fn run(source: &str) -> Result<Outcome, VmError> {
+    machine(source).run()
+}
+
+//nom de l'exception prédéfinie qui a arrêté la machine
+pub fn predefined(machine: &ZAM, outcome: &Outcome) -> Option<String> {
+    match outcome {
+        Outcome::Exception(Mlvalue::Block(h)) if machine.heap().tag(*h) == Ok(EXCEPTION_TAG) => {
+            let name = machine.heap().get(*h).ok()?[0].as_string().ok()?;
+            let name = machine.heap().get_string(name).ok()?;
+            Some(String::from_utf8_lossy(name).into_owned())
+        }
+        _ => None,
+    }
+}
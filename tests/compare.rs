@@ -0,0 +1,141 @@
+use std::cmp::Ordering;
+
+use mini_zam::{ErrorKind, Heap, Mlvalue, Outcome};
+
+mod common;
+
+fn run(source: &str) -> Result<Outcome, ErrorKind> {
+    common::run(source).map_err(|e| e.kind)
+}
+
+fn list(heap: &mut Heap, items: &[i64]) -> Mlvalue {
+    items.iter().rev().fold(Mlvalue::Entier(0), |tail, n| {
+        heap.alloc(vec![Mlvalue::Entier(*n), tail])
+    })
+}
+
+fn order(a: &Mlvalue, b: &Mlvalue, heap: &Heap) -> Option<Ordering> {
+    a.compare(b, heap, false).unwrap()
+}
+
+#[test]
+fn blocks_compare_by_tag_size_then_fields() {
+    let mut heap = Heap::new();
+    let a = list(&mut heap, &[1, 2, 3]);
+    let b = list(&mut heap, &[1, 2, 3]);
+    let c = list(&mut heap, &[1, 2, 4]);
+    let short = list(&mut heap, &[1, 2]);
+    assert_eq!(order(&a, &b, &heap), Some(Ordering::Equal));
+    assert_eq!(order(&a, &c, &heap), Some(Ordering::Less));
+    //[1; 2] est plus petit car sa fin [] est un entier
+    assert_eq!(order(&short, &a, &heap), Some(Ordering::Less));
+    assert_eq!(order(&Mlvalue::Entier(9), &a, &heap), Some(Ordering::Less));
+
+    let pair = heap.alloc(vec![Mlvalue::Entier(5), Mlvalue::Entier(0)]);
+    let triple = heap.alloc(vec![Mlvalue::Entier(0); 3]);
+    let tagged = heap.alloc_tagged(1, vec![Mlvalue::Entier(0)]);
+    assert_eq!(order(&pair, &triple, &heap), Some(Ordering::Less));
+    assert_eq!(order(&triple, &tagged, &heap), Some(Ordering::Less));
+}
+
+#[test]
+fn strings_and_floats_compare_by_content() {
+    let mut heap = Heap::new();
+    let ab = heap.alloc_string(b"ab".to_vec());
+    let ab2 = heap.alloc_string(b"ab".to_vec());
+    let abc = heap.alloc_string(b"abc".to_vec());
+    assert_eq!(order(&ab, &ab2, &heap), Some(Ordering::Equal));
+    assert_eq!(order(&ab, &abc, &heap), Some(Ordering::Less));
+
+    let nan = heap.alloc_float(f64::NAN);
+    let one = heap.alloc_float(1.0);
+    assert_eq!(order(&nan, &nan, &heap), None);
+    assert_eq!(nan.compare(&nan, &heap, true), Ok(Some(Ordering::Equal)));
+    assert_eq!(nan.compare(&one, &heap, true), Ok(Some(Ordering::Less)));
+    let zero = heap.alloc_float(0.0);
+    let minus_zero = heap.alloc_float(-0.0);
+    assert_eq!(order(&zero, &minus_zero, &heap), Some(Ordering::Equal));
+}
+
+#[test]
+fn polymorphic_primitives() {
+    let pairs = "\tCONST 2\n\tPUSH\n\tCONST 1\n\tMAKEBLOCK 2\n\tPUSH\n\
+                 \tCONST 2\n\tPUSH\n\tCONST 1\n\tMAKEBLOCK 2\n";
+    let test = |prim: &str| run(&format!("{}\tPRIM {}\n\tSTOP\n", pairs, prim));
+    let value = |n| Ok(Outcome::Value(Mlvalue::Entier(n)));
+    assert_eq!(test("="), value(1));
+    assert_eq!(test("<>"), value(0));
+    assert_eq!(test("<="), value(1));
+    assert_eq!(test("<"), value(0));
+    assert_eq!(test("compare"), value(0));
+    assert_eq!(test("=="), value(0));
+    assert_eq!(test("!="), value(1));
+
+    let nan = "\tCONSTFLOAT nan\n\tPUSH\n\tACC 0\n";
+    assert_eq!(run(&format!("{}\tPRIM =\n\tSTOP\n", nan)), value(0));
+    assert_eq!(run(&format!("{}\tPRIM <>\n\tSTOP\n", nan)), value(1));
+    assert_eq!(run(&format!("{}\tPRIM ==\n\tSTOP\n", nan)), value(1));
+    assert_eq!(run(&format!("{}\tPRIM compare\n\tSTOP\n", nan)), value(0));
+}
+
+#[test]
+fn functional_values_cannot_be_compared() {
+    let source = "\tBRANCH L2\nL1:\tACC 0\n\tRETURN 1\nL2:\tCLOSURE L1,0\n\tPUSH\n\
+                  \tCLOSURE L1,0\n\tPRIM =\n\tSTOP\n";
+    assert!(matches!(run(source), Err(ErrorKind::BadOperand(_))));
+}
+
+#[test]
+fn closures_against_blocks_are_functional_values() {
+    let mut heap = Heap::new();
+    let block = heap.alloc(vec![Mlvalue::Entier(1)]);
+    let closure = Mlvalue::Fermeture(0, vec![]);
+    for (a, b) in [(&closure, &block), (&block, &closure)].iter() {
+        assert!(matches!(
+            a.compare(b, &heap, false),
+            Err(ErrorKind::BadOperand(_))
+        ));
+    }
+}
+
+//l = 1 :: l
+const CYCLE: &str = "\tCONST 0\n\tPUSH\n\tCONST 1\n\tMAKEBLOCK 2\n\tPUSH\n\tPUSH\n\
+                     \tSETFIELD 1\n\tACC 0\n\tPUSH\n\tACC 0\n";
+
+#[test]
+fn cycles_hit_the_depth_limit() {
+    assert!(matches!(
+        run(&format!("{}\tPRIM =\n\tSTOP\n", CYCLE)),
+        Err(ErrorKind::BadOperand(_))
+    ));
+    //compare ne parcourt pas un bloc physiquement égal à l'autre
+    assert_eq!(
+        run(&format!("{}\tPRIM compare\n\tSTOP\n", CYCLE)),
+        Ok(Outcome::Value(Mlvalue::Entier(0)))
+    );
+    //hash s'arrête après un nombre borné de valeurs
+    assert!(run(&format!("{}\tPRIM hash\n\tSTOP\n", CYCLE)).is_ok());
+}
+
+#[test]
+fn hash_matches_ocaml() {
+    let mut heap = Heap::new();
+    assert_eq!(Mlvalue::Entier(0).hash(&heap), Ok(129913994));
+    assert_eq!(Mlvalue::Entier(1).hash(&heap), Ok(883721435));
+    let empty = heap.alloc_string(Vec::new());
+    assert_eq!(empty.hash(&heap), Ok(0));
+
+    //seules les 10 premières valeurs significatives comptent
+    let long = list(&mut heap, &(0..20).collect::<Vec<_>>());
+    let mut items: Vec<i64> = (0..20).collect();
+    items[15] = 99;
+    let other = list(&mut heap, &items);
+    assert_eq!(long.hash(&heap), other.hash(&heap));
+    items[5] = 99;
+    let different = list(&mut heap, &items);
+    assert_ne!(long.hash(&heap), different.hash(&heap));
+
+    let zero = heap.alloc_float(0.0);
+    let minus_zero = heap.alloc_float(-0.0);
+    assert_eq!(zero.hash(&heap), minus_zero.hash(&heap));
+}
@@ -0,0 +1,78 @@
+use mini_zam::{Debugger, Inst, Mlvalue, Outcome, Watch, ZAM};
+
+mod common;
+
+fn facto() -> ZAM {
+    common::file("rec_funs/facto.txt")
+}
+
+fn session(machine: &mut ZAM, debugger: &mut Debugger, script: &str) -> String {
+    let mut out = Vec::new();
+    debugger.repl(machine, script.as_bytes(), &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn breakpoint_and_backtrace() {
+    let mut machine = facto();
+    let mut debugger = Debugger::new();
+    //facto 5 s'arrête à l'entrée de la fonction pour 5, 4 puis 3
+    session(&mut machine, &mut debugger, "break L1\nc\nc\nc\n");
+    assert_eq!(machine.pc(), 1);
+    assert_eq!(machine.stack().last(), Some(&Mlvalue::Entier(3)));
+
+    let frames = machine.backtrace();
+    let pcs: Vec<usize> = frames.iter().map(|f| f.pc).collect();
+    assert_eq!(pcs, vec![1, 14, 14, 23]);
+    assert!(frames[1..]
+        .iter()
+        .all(|f| matches!(machine.prog()[f.pc].1, Inst::Apply(1))));
+}
+
+#[test]
+fn finish_returns_to_caller() {
+    let mut machine = facto();
+    let mut debugger = Debugger::new();
+    let out = session(
+        &mut machine,
+        &mut debugger,
+        "break L1\nc\nc\ndelete L1\nfinish\n",
+    );
+    assert_eq!(machine.pc(), 15);
+    //l'appel interne a renvoyé 4!
+    assert_eq!(machine.accu(), &Mlvalue::Entier(24));
+    assert!(out.contains("=> 15 (L3+7)\tPUSH"));
+}
+
+#[test]
+fn next_steps_over_apply() {
+    let mut machine = facto();
+    let mut debugger = Debugger::new();
+    session(&mut machine, &mut debugger, "break 23\nc\nnext\n");
+    assert_eq!(machine.pc(), 24);
+    assert_eq!(machine.accu(), &Mlvalue::Entier(120));
+}
+
+#[test]
+fn watch_stops_on_change() {
+    let mut machine = facto();
+    let mut debugger = Debugger::new();
+    debugger.add_watch(&machine, Watch::Slot(1));
+    let out = session(&mut machine, &mut debugger, "c\n");
+    //CLOSUREREC remplit la case 0, PUSH la case 1 avec 5
+    assert!(out.contains("case 1 de la pile : absente -> 5"));
+    assert_eq!(machine.stack().len(), 2);
+}
+
+#[test]
+fn run_to_the_end() {
+    let mut machine = facto();
+    let mut debugger = Debugger::new();
+    let out = session(&mut machine, &mut debugger, "c\nstep\nq\n");
+    assert!(out.contains("fin : 120"));
+    assert!(out.contains("le programme est terminé"));
+    assert!(matches!(
+        debugger.finished,
+        Some(Ok(Outcome::Value(Mlvalue::Entier(120))))
+    ));
+}
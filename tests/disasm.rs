@@ -0,0 +1,59 @@
+use mini_zam::linker::link;
+use mini_zam::{disasm, disasm_linked, load_file, load_str, DisasmOptions};
+
+use std::fs;
+use std::path::Path;
+
+const PROG: &str = "\tBRANCH L3
+R0:\tRESTART
+L1:\tGRAB 1
+\tACC 1
+\tPUSH
+\tACC 1
+\tPRIM +
+\tRETURN 2
+L3:\tCLOSURE L1,0
+\tSTOP
+";
+
+#[test]
+fn plain_output_is_the_source() {
+    let code = load_str(PROG, "prog").unwrap();
+    assert_eq!(disasm(&code, &DisasmOptions::default()), PROG);
+
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/unary_funs/fun1.txt");
+    let code = load_file(&path).unwrap();
+    let text = disasm(&code, &DisasmOptions::default());
+    assert_eq!(text, fs::read_to_string(&path).unwrap());
+}
+
+#[test]
+fn annotated_output_round_trips() {
+    let code = load_str(PROG, "prog").unwrap();
+    let options = DisasmOptions {
+        indices: true,
+        annotate: true,
+        ..DisasmOptions::default()
+    };
+    let text = disasm(&code, &options);
+    assert_eq!(
+        text.lines().nth(8),
+        Some("L3:\tCLOSURE L1,0        ; 8, vers 2, arité 2, 0 libres")
+    );
+    assert_eq!(load_str(&text, "disasm").unwrap(), code);
+}
+
+#[test]
+fn synthesized_labels() {
+    let code = load_str(PROG, "prog").unwrap();
+    let options = DisasmOptions {
+        synthesize_labels: true,
+        ..DisasmOptions::default()
+    };
+    let text = disasm(&code, &options);
+    assert!(text.starts_with("\tBRANCH L8\nL1:\tRESTART\nL2:\tGRAB 1\n"));
+
+    //un programme lié n'a plus de noms, chaque cible en reçoit un
+    let linked = disasm_linked(&link(&code).unwrap(), &DisasmOptions::default());
+    assert_eq!(linked, text.replace("L1:\tRESTART", "\tRESTART"));
+}
@@ -0,0 +1,177 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use mini_zam::{
+    compile_str, load_str, ErrorKind, Inst, LinkError, LoadError, Mlvalue, Outcome, TraceLevel,
+    TraceSink, ZAM,
+};
+
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn run_text_program() {
+    let code = load_str("\tCONST 2\n\tPUSH\n\tCONST 40\n\tPRIM +\n\tSTOP\n", "prog").unwrap();
+    let mut machine = ZAM::new(&code);
+    assert_eq!(machine.run().unwrap(), Outcome::Value(Mlvalue::Entier(42)));
+    assert_eq!(machine.accu(), &Mlvalue::Entier(42));
+    assert!(machine.stack().is_empty());
+}
+
+#[test]
+fn parse_errors_are_reported() {
+    match load_str("\tCONST x\n\tFOO\n", "prog") {
+        Err(LoadError::Parse(errors)) => assert_eq!(errors.len(), 2),
+        _ => panic!("erreurs d'analyse attendues"),
+    }
+}
+
+fn compile_error(source: &str) -> String {
+    match compile_str(source) {
+        Err(LoadError::Compile(e)) => e.to_string(),
+        _ => panic!("erreur de compilation attendue"),
+    }
+}
+
+#[test]
+fn compile_errors_point_at_the_source() {
+    assert_eq!(
+        compile_error("let x = 1\nlet rec y = x + 1"),
+        "2:9: seules les fonctions peuvent être récursives"
+    );
+    assert_eq!(
+        compile_error("let x = 1 in\n  let rec y = 2 in y"),
+        "2:11: seules les fonctions peuvent être récursives"
+    );
+    assert_eq!(
+        compile_error("exception Foo\nexception"),
+        "2:10: `fin de fichier` trouvé, un nom d'exception attendu"
+    );
+    assert_eq!(
+        compile_error("let x = 1 +"),
+        "1:12: `fin de fichier` trouvé, une expression attendu"
+    );
+}
+
+#[test]
+fn host_callback() {
+    let code = compile_str("let x = 20 in x + 1").unwrap();
+    let mut code = code;
+    //remplace le STOP final par un appel à la primitive de l'hôte
+    code.insert(code.len() - 1, (None, Inst::Prim("double".to_string())));
+    let mut machine = ZAM::new(&code);
+    machine.register_callback(
+        "double",
+        Box::new(|v, _heap| Ok(Mlvalue::Entier(2 * v.as_int()?))),
+    );
+    assert_eq!(machine.run().unwrap(), Outcome::Value(Mlvalue::Entier(42)));
+}
+
+#[test]
+fn unknown_primitive() {
+    let code = load_str("\tPRIM inconnue\n\tSTOP\n", "prog").unwrap();
+    let err = ZAM::new(&code).run().unwrap_err();
+    assert_eq!(err.pc, 0);
+    assert_eq!(
+        err.kind,
+        ErrorKind::UnknownPrimitive("inconnue".to_string())
+    );
+}
+
+#[test]
+fn trace_is_separate_from_output() {
+    let code = load_str("\tCONST 65\n\tPRIM print\n\tSTOP\n", "prog").unwrap();
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut machine = ZAM::new(&code);
+    machine.set_output(Box::new(SharedBuffer(output.clone())));
+    machine.set_trace(TraceLevel::Inst, TraceSink::Buffer(Vec::new()));
+    machine.run().unwrap();
+    assert_eq!(&*output.borrow(), b"A");
+    assert_eq!(
+        machine.tracer().lines(),
+        [
+            "Instruction 0 : Const(65)",
+            "Instruction 1 : Prim(\"print\")",
+            "Instruction 2 : Stop",
+            "Valeur de fin de Code Entier(65)",
+        ]
+    );
+}
+
+#[test]
+fn trace_callback_receives_levels() {
+    let code = load_str("\tCONST 1\n\tSTOP\n", "prog").unwrap();
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let log = seen.clone();
+    let mut machine = ZAM::new(&code);
+    machine.set_trace(
+        TraceLevel::Result,
+        TraceSink::Callback(Box::new(move |level, line| {
+            log.borrow_mut().push((level, line.to_string()))
+        })),
+    );
+    machine.run().unwrap();
+    assert_eq!(
+        *seen.borrow(),
+        [(
+            TraceLevel::Result,
+            "Valeur de fin de Code Entier(1)".to_string()
+        )]
+    );
+}
+
+#[test]
+fn link_errors_are_reported_at_load() {
+    match load_str("L1:\tBRANCH L2\nL1:\tSTOP\n\tCLOSURE L3,0\n", "prog") {
+        Err(LoadError::Link(errors)) => assert_eq!(
+            errors,
+            vec![
+                LinkError::Duplicate {
+                    label: "L1".to_string(),
+                    first: 0,
+                    second: 1,
+                },
+                LinkError::Undefined {
+                    label: "L2".to_string(),
+                    pc: 0,
+                },
+                LinkError::Undefined {
+                    label: "L3".to_string(),
+                    pc: 2,
+                },
+            ]
+        ),
+        _ => panic!("erreurs de liaison attendues"),
+    }
+}
+
+#[test]
+fn unlinked_label_fails_when_reached() {
+    let code = vec![
+        (None, Inst::Const(0)),
+        (None, Inst::BranchIfNot("absent".to_string())),
+    ];
+    let err = ZAM::new(&code).run().unwrap_err();
+    assert_eq!(err.pc, 1);
+    assert_eq!(err.kind, ErrorKind::UnknownLabel("absent".to_string()));
+}
+
+#[test]
+fn default_tracer_is_silent() {
+    let code = load_str("\tCONST 1\n\tSTOP\n", "prog").unwrap();
+    let mut machine = ZAM::new(&code);
+    machine.set_option("Debug");
+    machine.run().unwrap();
+    assert!(matches!(machine.tracer().sink, TraceSink::Null));
+}
@@ -0,0 +1,85 @@
+use mini_zam::mlvalue::string_of_float;
+use mini_zam::{load_str, read_bytecode, write_bytecode, Heap, Inst, Mlvalue, Outcome};
+
+mod common;
+
+#[test]
+fn string_of_float_matches_ocaml() {
+    let cases = [
+        (0.0, "0."),
+        (-0.0, "-0."),
+        (1.0, "1."),
+        (-2.5, "-2.5"),
+        (0.1, "0.1"),
+        (1.0 / 3.0, "0.333333333333"),
+        (123456789012.0, "123456789012."),
+        (1234567890123.0, "1.23456789012e+12"),
+        (1e15, "1e+15"),
+        (0.0001, "0.0001"),
+        (0.00001, "1e-05"),
+        (1.5e-300, "1.5e-300"),
+        (999999999999.5, "1e+12"),
+        (f64::INFINITY, "inf"),
+        (f64::NEG_INFINITY, "-inf"),
+        (f64::NAN, "nan"),
+    ];
+    for (x, expected) in cases.iter() {
+        assert_eq!(string_of_float(*x), *expected, "{:?}", x);
+    }
+}
+
+#[test]
+fn constants_round_trip_through_text_and_bytecode() {
+    for x in [0.1, -0.0, 1e300, 5e-324, f64::INFINITY, f64::NEG_INFINITY].iter() {
+        let inst = Inst::ConstFloat(*x);
+        let code = load_str(&format!("\t{}\n\tSTOP\n", inst), "prog").unwrap();
+        assert_eq!(code[0].1, inst);
+        let mut bytes = Vec::new();
+        write_bytecode(&code, &mut bytes, false).unwrap();
+        let read = read_bytecode(&mut bytes.as_slice()).unwrap();
+        match read[0].1 {
+            Inst::ConstFloat(y) => assert_eq!(y.to_bits(), x.to_bits()),
+            ref other => panic!("{:?}", other),
+        }
+    }
+    assert!(load_str("\tCONSTFLOAT 1.5x\n", "prog").is_err());
+}
+
+#[test]
+fn nan_constants_are_parsed() {
+    let code = load_str("\tCONSTFLOAT nan\n\tSTOP\n", "prog").unwrap();
+    match code[0].1 {
+        Inst::ConstFloat(x) => assert!(x.is_nan()),
+        ref other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn results_are_fresh_boxes() {
+    let mut machine =
+        common::machine("\tCONSTFLOAT 2.0\n\tPUSH\n\tCONSTFLOAT 1.0\n\tPRIM -.\n\tSTOP\n");
+    let result = match machine.run().unwrap() {
+        Outcome::Value(v) => v,
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(result, Mlvalue::Flottant(2));
+    assert_eq!(result.as_float(machine.heap()).unwrap(), -1.0);
+}
+
+#[test]
+fn integer_primitives_reject_floats() {
+    assert!(common::run("\tCONST 1\n\tPUSH\n\tCONSTFLOAT 1.0\n\tPRIM +\n\tSTOP\n").is_err());
+}
+
+#[test]
+fn unreachable_floats_are_collected() {
+    let mut heap = Heap::new();
+    let kept = heap.alloc_floats(vec![1.0, 2.0]);
+    heap.alloc_float(3.0);
+    let x = heap.alloc_float(4.0);
+    let block = heap.alloc(vec![kept.clone(), x.clone()]);
+    heap.collect(&[block]);
+    assert_eq!(heap.stats().live, 3);
+    assert_eq!(heap.show(&kept), "Flottants([1., 2.])");
+    assert_eq!(x.as_float(&heap).unwrap(), 4.0);
+}
@@ -0,0 +1,17 @@
+(* flottants : littéraux, opérateurs et conversions *)
+let pi = 4. *. 0.785398163397
+
+let area r = pi *. r ** 2.
+
+let () = print_float (area 2.)
+let () = print_char '\n'
+
+let rec sum n = if n = 0 then 0. else float_of_int n +. sum (n - 1)
+
+let () = print_string (string_of_float (sum 100) ^ "\n")
+let () = print_float (-. (1.5e-5 -. -2.))
+let () = print_char '\n'
+let () = print_float (exp 1. +. log 1. +. sin 0. +. cos 0.)
+let () = print_char '\n'
+
+let _ = int_of_float (sqrt 1_000_000.)
@@ -0,0 +1,129 @@
+//exécute chaque programme de tests/ et compare son résultat au fichier
+//<programme>.expected voisin ; `cargo test --test golden -- --bless`
+//réécrit les fichiers attendus
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use mini_zam::{optimize, Outcome, Passes, Program, ZAM};
+
+//sortie partagée entre la machine et le harnais
+#[derive(Clone, Default)]
+struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn programs(dir: &Path, found: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .expect("lecture de tests/")
+        .map(|e| e.unwrap().path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            programs(&path, found);
+        } else if matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("txt") | Some("ml")
+        ) {
+            found.push(path);
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<Program, String> {
+    let code = mini_zam::load_file(path).map_err(|e| e.to_string())?;
+    if path.extension().and_then(|e| e.to_str()) == Some("ml") {
+        Ok(code)
+    } else {
+        Ok(optimize(&code, &Passes::default()))
+    }
+}
+
+//résultat d'un programme dans le format des fichiers .expected ; un
+//programme qui ne se charge pas est toujours un échec, jamais béni
+fn run(path: &Path) -> Result<String, String> {
+    let code = load(path)?;
+    let capture = Capture::default();
+    let mut machine = ZAM::new(&code);
+    machine.set_output(Box::new(capture.clone()));
+    let result = match machine.run() {
+        Ok(Outcome::Value(v)) => format!("valeur: {}\n", machine.heap().show(&v)),
+        Ok(Outcome::Exception(v)) => format!("exception: {}\n", machine.heap().show(&v)),
+        Ok(outcome) => format!("issue: {:?}\n", outcome),
+        Err(e) => format!("erreur: {}\n", e),
+    };
+    let output = String::from_utf8_lossy(&capture.0.borrow()).into_owned();
+    Ok(format!("{}sortie: {:?}\n", result, output))
+}
+
+fn main() {
+    let bless = std::env::args().any(|a| a == "--bless");
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let mut found = Vec::new();
+    programs(&root, &mut found);
+
+    let mut failures = Vec::new();
+    for path in &found {
+        let mut expected_path = path.clone().into_os_string();
+        expected_path.push(".expected");
+        let expected_path = PathBuf::from(expected_path);
+        let name = path.strip_prefix(&root).unwrap().display().to_string();
+        let actual = match run(path) {
+            Ok(actual) => actual,
+            Err(e) => {
+                println!("{} ... ÉCHEC", name);
+                failures.push(format!("{}\nchargement impossible : {}", name, e));
+                continue;
+            }
+        };
+
+        if bless {
+            fs::write(&expected_path, &actual).expect("écriture du fichier attendu");
+            println!("{} ... béni", name);
+            continue;
+        }
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => println!("{} ... ok", name),
+            Ok(expected) => {
+                println!("{} ... ÉCHEC", name);
+                failures.push(format!(
+                    "{}\n--- attendu\n{}--- obtenu\n{}",
+                    name, expected, actual
+                ));
+            }
+            Err(_) => {
+                println!("{} ... ÉCHEC", name);
+                failures.push(format!(
+                    "{}\nfichier {} absent, relancer avec --bless",
+                    name,
+                    expected_path.display()
+                ));
+            }
+        }
+    }
+
+    println!();
+    for f in &failures {
+        println!("{}\n", f);
+    }
+    println!(
+        "résultat : {} programmes, {} échecs",
+        found.len(),
+        failures.len()
+    );
+    if !failures.is_empty() {
+        std::process::exit(1);
+    }
+}
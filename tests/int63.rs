@@ -0,0 +1,133 @@
+use mini_zam::{load_file, IntMode, Mlvalue, Outcome};
+
+use std::fs;
+use std::path::Path;
+
+mod common;
+
+const MAX_INT: i64 = (1 << 62) - 1;
+const MIN_INT: i64 = -(1 << 62);
+
+fn run(source: &str, ints: IntMode) -> Outcome {
+    let mut machine = common::compiled(source);
+    machine.set_int_mode(ints);
+    machine.run().unwrap()
+}
+
+fn ocaml(source: &str) -> i64 {
+    match run(source, IntMode::OCaml) {
+        Outcome::Value(Mlvalue::Entier(n)) => n,
+        other => panic!("entier attendu, {:?}", other),
+    }
+}
+
+//sans débordement les deux modes donnent le même résultat
+#[test]
+fn unary_funs_agree_in_both_modes() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/unary_funs");
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|e| e.to_str()) != Some("txt") {
+            continue;
+        }
+        let code = load_file(&path).unwrap();
+        let mut results = Vec::new();
+        for ints in [IntMode::Native, IntMode::OCaml].iter() {
+            let mut machine = common::quiet(&code);
+            machine.set_output(Box::new(std::io::sink()));
+            machine.set_int_mode(*ints);
+            results.push(machine.run().unwrap());
+        }
+        assert_eq!(results[0], results[1], "{}", path.display());
+    }
+}
+
+//arithexpr.ml : (2*3)+4
+#[test]
+fn arithexpr_wraps_at_63_bits() {
+    assert_eq!(ocaml("(2*3)+4"), 10);
+    assert_eq!(ocaml("(4611686018427387903*2)+4"), 2);
+    assert_eq!(ocaml("(4611686018427387903*3)+4"), MIN_INT + 1);
+    assert_eq!(
+        run("(4611686018427387903*2)+4", IntMode::Native),
+        Outcome::Value(Mlvalue::Entier((MAX_INT * 2).wrapping_add(4)))
+    );
+}
+
+//const.ml : 42
+#[test]
+fn const_extremes() {
+    assert_eq!(ocaml("4611686018427387903"), MAX_INT);
+    assert_eq!(ocaml("-4611686018427387904"), MIN_INT);
+    assert_eq!(ocaml("4611686018427387903 + 1"), MIN_INT);
+    assert_eq!(ocaml("-4611686018427387904 - 1"), MAX_INT);
+}
+
+//fun1.ml : let f x= 1 + x in (f 4)* 2
+#[test]
+fn fun1_overflowing_argument() {
+    assert_eq!(ocaml("let f x= 1 + x in (f 4)* 2"), 10);
+    assert_eq!(ocaml("let f x= 1 + x in (f 4611686018427387903)* 2"), 0);
+    assert_eq!(
+        ocaml("let f x= 1 + x in (f 2305843009213693951)* 2"),
+        MIN_INT
+    );
+}
+
+//fun2.ml : (fun x -> (fun y -> x + y) 2) 3
+#[test]
+fn fun2_negation_and_abs() {
+    assert_eq!(ocaml("(fun x -> (fun y -> x + y) 2) 3"), 5);
+    assert_eq!(
+        ocaml("(fun x -> (fun y -> - (x + y)) 2) 4611686018427387902"),
+        MIN_INT
+    );
+    assert_eq!(ocaml("(fun x -> abs x) (-4611686018427387904)"), MIN_INT);
+}
+
+//fun5.ml : trois fonctions imbriquées ajoutent 1 chacune
+#[test]
+fn fun5_nested_increments() {
+    let fun5 = |n: &str| {
+        format!(
+            "let f x =\n  let g y =\n    let h z = z + 1\n    in\n    (h y) + 1\n  in\n  (g x) + 1\nin\nf {}",
+            n
+        )
+    };
+    assert_eq!(ocaml(&fun5("38")), 41);
+    assert_eq!(ocaml(&fun5("4611686018427387900")), MAX_INT);
+    assert_eq!(ocaml(&fun5("4611686018427387901")), MIN_INT);
+}
+
+#[test]
+fn division_truncates_towards_zero() {
+    assert_eq!(ocaml("(-7) / 2"), -3);
+    assert_eq!(ocaml("(-7) mod 2"), -1);
+    assert_eq!(ocaml("7 / (-2)"), -3);
+    assert_eq!(ocaml("7 mod (-2)"), 1);
+    assert_eq!(ocaml("-4611686018427387904 / (-1)"), MIN_INT);
+    assert_eq!(ocaml("-4611686018427387904 mod (-1)"), 0);
+    assert_eq!(
+        run("(-9223372036854775807 - 1) / (-1)", IntMode::Native),
+        Outcome::Value(Mlvalue::Entier(i64::MIN))
+    );
+}
+
+#[test]
+fn shifts_depend_on_the_width() {
+    assert_eq!(ocaml("1 lsl 62"), MIN_INT);
+    assert_eq!(ocaml("1 lsl 63"), 0);
+    assert_eq!(ocaml("(-1) lsr 1"), MAX_INT);
+    assert_eq!(ocaml("(-1) lsr 61"), 3);
+    assert_eq!(ocaml("(-1) asr 62"), -1);
+    assert_eq!(ocaml("(-1) asr 70"), -1);
+    assert_eq!(ocaml("4611686018427387903 land (-1)"), MAX_INT);
+    assert_eq!(
+        run("(-1) lsr 61", IntMode::Native),
+        Outcome::Value(Mlvalue::Entier(7))
+    );
+    assert_eq!(
+        run("1 lsl 63", IntMode::Native),
+        Outcome::Value(Mlvalue::Entier(i64::MIN))
+    );
+}
@@ -0,0 +1,108 @@
+use mini_zam::{compile_str, ErrorKind, Mlvalue, Outcome};
+
+mod common;
+
+#[test]
+fn out_of_fuel_and_resume() {
+    let mut machine = common::file("rec_funs/facto.txt");
+    machine.set_fuel(10);
+    assert_eq!(machine.run().unwrap(), Outcome::OutOfFuel);
+    assert_eq!(machine.steps(), 10);
+    //sans nouveau carburant la machine ne repart pas
+    let pc = machine.pc();
+    assert_eq!(machine.run().unwrap(), Outcome::OutOfFuel);
+    assert_eq!(machine.pc(), pc);
+
+    //95 instructions en tout
+    let mut resumes = 1;
+    let mut outcome = machine.resume(10).unwrap();
+    while outcome == Outcome::OutOfFuel {
+        resumes += 1;
+        outcome = machine.resume(10).unwrap();
+    }
+    assert_eq!(outcome, Outcome::Value(Mlvalue::Entier(120)));
+    assert_eq!(resumes, 9);
+    assert_eq!(machine.steps(), 95);
+}
+
+#[test]
+fn step_limit_is_still_an_error() {
+    let mut machine = common::file("rec_funs/facto.txt");
+    machine.set_max_steps(10);
+    machine.set_fuel(100);
+    let err = machine.run().unwrap_err();
+    assert_eq!(err.kind, ErrorKind::StepLimit(10));
+}
+
+#[test]
+fn stack_overflow() {
+    let code = compile_str("let rec f n = if n = 0 then 0 else 1 + f (n - 1) in\nf 1000").unwrap();
+    let mut machine = common::quiet(&code);
+    machine.set_max_stack(100);
+    assert_eq!(machine.run().unwrap(), Outcome::StackOverflow);
+    assert_eq!(machine.stack().len(), 101);
+
+    let mut machine = common::quiet(&code);
+    machine.set_max_stack(10_000);
+    assert_eq!(
+        machine.run().unwrap(),
+        Outcome::Value(Mlvalue::Entier(1000))
+    );
+}
+
+#[test]
+fn heap_limit_counts_live_words_only() {
+    //une liste de 50 éléments reste vivante jusqu'à la fin
+    let mut machine = common::file("block_values/liste_length.txt");
+    machine.set_max_heap(2);
+    assert_eq!(machine.run().unwrap(), Outcome::HeapExhausted);
+
+    //des blocs aussitôt abandonnés sont ramassés
+    let mut machine = common::compiled(
+        "let rec loop n = if n = 0 then 0 else (let a = (n, n) in fst a - n) + loop (n - 1) in\nloop 500",
+    );
+    //une paire vivante occupe trois mots
+    machine.set_max_heap(3);
+    assert_eq!(machine.run().unwrap(), Outcome::Value(Mlvalue::Entier(0)));
+    assert!(machine.heap().stats().collections > 0);
+}
+
+#[test]
+fn heap_limit_does_not_collect_at_every_step() {
+    //une liste de 180 mots reste vivante, chaque tour abandonne une paire
+    let mut machine = common::compiled(
+        "let rec build n = if n = 0 then [] else n :: build (n - 1) in\n\
+         let l = build 60 in\n\
+         let rec loop n = if n = 0 then 0 else (let a = (n, n) in fst a - n) + loop (n - 1) in\n\
+         loop 500 + (match l with [] -> 0 | x :: _ -> x)",
+    );
+    machine.set_max_heap(200);
+    assert_eq!(machine.run().unwrap(), Outcome::Value(Mlvalue::Entier(60)));
+    assert!(machine.heap().stats().collections < 20);
+}
+
+#[test]
+fn heap_limit_counts_string_bytes() {
+    //la chaîne double à chaque appel, seule la dernière reste vivante
+    let mut machine = common::compiled(
+        "let rec grow s n = if n = 0 then 0 else grow (s ^ s) (n - 1) in\ngrow \"ab\" 40",
+    );
+    machine.set_max_heap(1000);
+    assert_eq!(machine.run().unwrap(), Outcome::HeapExhausted);
+    assert!(machine.heap().stats().peak_words <= 1000);
+    //arrêtée sur le PRIM avec ses arguments rempilés
+    assert_eq!(machine.prog()[machine.pc()].1.mnemonic(), "PRIM");
+}
+
+#[test]
+fn huge_float_array_exhausts_the_heap() {
+    let source = format!(
+        "\tCONSTFLOAT 1.5\n\tPUSH\n\tCONST {}\n\tPRIM float_array_make\n\tSTOP\n",
+        i64::MAX
+    );
+    assert_eq!(common::run(&source).unwrap(), Outcome::HeapExhausted);
+
+    let mut machine = common::machine(&source);
+    machine.set_max_heap(1 << 20);
+    assert_eq!(machine.run().unwrap(), Outcome::HeapExhausted);
+}
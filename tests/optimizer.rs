@@ -0,0 +1,101 @@
+use mini_zam::{load_str, optimize, Inst, Passes, Program};
+
+fn only(pass: &str) -> Passes {
+    let mut passes = Passes::none();
+    assert!(passes.set(pass, true));
+    passes
+}
+
+fn prog(source: &str) -> Program {
+    load_str(source, "prog").unwrap()
+}
+
+fn label(l: &str) -> Option<String> {
+    Some(l.to_string())
+}
+
+#[test]
+fn appterm_keeps_labels() {
+    let code = prog("L1:\tAPPLY 2\n\tRETURN 1\n");
+    assert_eq!(
+        optimize(&code, &only("appterm")),
+        vec![(label("L1"), Inst::AppTerm(2, 3))]
+    );
+
+    //le RETURN désigné par un saut reste en place
+    let code = prog("\tBRANCH L2\n\tAPPLY 1\nL2:\tRETURN 1\n");
+    assert_eq!(
+        optimize(&code, &only("appterm")),
+        vec![
+            (None, Inst::Branch("L2".to_string())),
+            (None, Inst::AppTerm(1, 2)),
+            (label("L2"), Inst::Return(1)),
+        ]
+    );
+}
+
+#[test]
+fn push_pop_moves_label_forward() {
+    let code = prog("\tBRANCH L1\nL1:\tPUSH\n\tPOP\n\tCONST 1\n\tSTOP\n");
+    assert_eq!(
+        optimize(&code, &only("push-pop")),
+        vec![
+            (None, Inst::Branch("L1".to_string())),
+            (label("L1"), Inst::Const(1)),
+            (None, Inst::Stop),
+        ]
+    );
+}
+
+#[test]
+fn push_pop_nested() {
+    let code = prog("\tPUSH\n\tPUSH\n\tPOP\n\tPOP\n\tSTOP\n");
+    assert_eq!(optimize(&code, &only("push-pop")), vec![(None, Inst::Stop)]);
+}
+
+#[test]
+fn jumps_are_threaded() {
+    let code = prog("\tBRANCHIFNOT L1\n\tSTOP\nL1:\tBRANCH L2\nL2:\tBRANCH L3\nL3:\tSTOP\n");
+    let optimized = optimize(&code, &only("jumps"));
+    //les BRANCH vers l'instruction suivante disparaissent et L1, L2, L3
+    //désignent le même STOP
+    assert_eq!(
+        optimized,
+        vec![
+            (None, Inst::BranchIfNot("L1".to_string())),
+            (None, Inst::Stop),
+            (label("L1"), Inst::Stop),
+        ]
+    );
+}
+
+#[test]
+fn jump_cycles_terminate() {
+    let code = prog("L1:\tBRANCH L2\nL2:\tBRANCH L1\n");
+    assert_eq!(
+        optimize(&code, &only("jumps")),
+        vec![(label("L1"), Inst::Branch("L1".to_string()))]
+    );
+}
+
+#[test]
+fn dead_code_after_terminators() {
+    let code = prog(
+        "\tBRANCH L1\n\tCONST 1\nL0:\tCONST 2\nL1:\tCONST 3\n\tSTOP\n\tCONST 4\nLR:\tRESTART\nL2:\tGRAB 1\n",
+    );
+    assert_eq!(
+        optimize(&code, &only("dead-code")),
+        vec![
+            (None, Inst::Branch("L1".to_string())),
+            (label("L1"), Inst::Const(3)),
+            (None, Inst::Stop),
+            (label("LR"), Inst::Restart),
+            (label("L2"), Inst::Grab(1)),
+        ]
+    );
+}
+
+#[test]
+fn unknown_pass() {
+    assert!(!Passes::default().set("inline", false));
+}
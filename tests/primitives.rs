@@ -0,0 +1,137 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use mini_zam::{load_str, ErrorKind, LinkError, Mlvalue, Outcome, Primitives, ZAM};
+
+mod common;
+
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn host_primitive_with_arity() {
+    //clamp x lo hi, x dans l'accu puis lo et hi dans la pile
+    let code = load_str(
+        "\tCONST 100\n\tPUSH\n\tCONST 10\n\tPUSH\n\tCONST 42\n\tPRIM clamp\n\tSTOP\n",
+        "prog",
+    )
+    .unwrap();
+    let mut primitives = Primitives::new();
+    primitives.register(
+        "clamp",
+        3,
+        Box::new(|args, _| {
+            let x = args[0].as_int()?;
+            Ok(Mlvalue::Entier(
+                x.max(args[1].as_int()?).min(args[2].as_int()?),
+            ))
+        }),
+    );
+    let mut machine = ZAM::with_primitives(&code, primitives).ok().unwrap();
+    assert_eq!(machine.run().unwrap(), Outcome::Value(Mlvalue::Entier(42)));
+    assert!(machine.stack().is_empty());
+}
+
+#[test]
+fn unknown_primitives_are_load_errors() {
+    let code = load_str("\tPRIM foo\n\tPRIM +\n\tPRIM bar\n\tSTOP\n", "prog").unwrap();
+    match ZAM::with_primitives(&code, Primitives::new()) {
+        Err(errors) => assert_eq!(
+            errors,
+            vec![
+                LinkError::UnknownPrimitive {
+                    name: "foo".to_string(),
+                    pc: 0
+                },
+                LinkError::UnknownPrimitive {
+                    name: "bar".to_string(),
+                    pc: 2
+                },
+            ]
+        ),
+        Ok(_) => panic!("primitives inconnues attendues"),
+    }
+}
+
+#[test]
+fn registered_after_load_and_overriding_builtins() {
+    let mut machine =
+        common::machine("\tCONST 2\n\tPUSH\n\tCONST 3\n\tPRIM +\n\tPRIM answer\n\tSTOP\n");
+    machine.register_primitive(
+        "+",
+        2,
+        Box::new(|args, _| Ok(Mlvalue::Entier(args[0].as_int()? * args[1].as_int()?))),
+    );
+    //sans argument, l'accu est ignoré
+    machine.register_primitive(
+        "answer",
+        0,
+        Box::new(|args, _| {
+            assert!(args.is_empty());
+            Ok(Mlvalue::Entier(42))
+        }),
+    );
+    assert_eq!(machine.run().unwrap(), Outcome::Value(Mlvalue::Entier(42)));
+    assert_eq!(machine.primitive_at(3).unwrap().name, "+");
+}
+
+#[test]
+fn primitives_write_to_the_program_output() {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut machine = common::machine("\tCONST 72\n\tPRIM shout\n\tSTOP\n");
+    machine.set_output(Box::new(SharedBuffer(output.clone())));
+    machine.register_primitive(
+        "shout",
+        1,
+        Box::new(|args, context| {
+            let c = args[0].as_int()? as u8 as char;
+            write!(context.output, "{}!", c).map_err(|e| ErrorKind::Host(e.to_string()))?;
+            Ok(args[0].clone())
+        }),
+    );
+    assert_eq!(machine.run().unwrap(), Outcome::Value(Mlvalue::Entier(72)));
+    assert_eq!(&*output.borrow(), b"H!");
+}
+
+#[test]
+fn predefined_exceptions_are_not_integers() {
+    //Division_by_zero levée par la primitive / ne se confond pas avec RAISE 1
+    let mut machine = common::machine("\tCONST 0\n\tPUSH\n\tCONST 1\n\tPRIM /\n\tSTOP\n");
+    let outcome = machine.run().unwrap();
+    assert_eq!(
+        common::predefined(&machine, &outcome).as_deref(),
+        Some("Division_by_zero")
+    );
+    let mut machine = common::machine("\tCONST 1\n\tRAISE\n\tSTOP\n");
+    let outcome = machine.run().unwrap();
+    assert_eq!(outcome, Outcome::Exception(Mlvalue::Entier(1)));
+    assert_eq!(common::predefined(&machine, &outcome), None);
+
+    //PRIM Division_by_zero charge la même exception, que = reconnaît
+    let source = "\tPUSHTRAP L1\n\tCONST 0\n\tPUSH\n\tCONST 1\n\tPRIM /\n\tPOPTRAP\n\tSTOP\n\
+                  L1:\tPUSH\n\tPRIM Division_by_zero\n\tPRIM =\n\tSTOP\n";
+    assert_eq!(
+        common::run(source).unwrap(),
+        Outcome::Value(Mlvalue::Entier(1))
+    );
+}
+
+#[test]
+fn declared_exceptions_are_numbered_from_zero() {
+    let mut machine = common::compiled("exception A\nexception B\nlet _ = raise B");
+    assert_eq!(
+        machine.run().unwrap(),
+        Outcome::Exception(Mlvalue::Entier(1))
+    );
+}
@@ -0,0 +1,61 @@
+use mini_zam::{ProfileFormat, ZAM};
+
+mod common;
+
+fn machine(file: &str, stacks: bool) -> ZAM {
+    let mut machine = common::file(file);
+    machine.enable_profiling(stacks);
+    machine.run().unwrap();
+    machine
+}
+
+#[test]
+fn counts_per_instruction_pc_and_function() {
+    let machine = machine("rec_funs/facto.txt", false);
+    let profile = machine.profile().unwrap();
+    assert_eq!(profile.total, machine.steps());
+    assert_eq!(profile.by_pc.iter().sum::<u64>(), profile.total);
+    assert_eq!(profile.by_inst.values().sum::<u64>(), profile.total);
+
+    //facto 5 : un appel depuis le code principal, cinq appels récursifs
+    assert_eq!(profile.calls.get("L1"), Some(&6));
+    assert_eq!(profile.by_inst.get("APPLY"), Some(&6));
+    //le test n = 0 en tête de la fonction est exécuté à chaque appel
+    assert_eq!(profile.by_pc[1], 6);
+    assert_eq!(profile.function(1), "L1");
+    assert_eq!(profile.function(0), "<main>");
+    assert!(profile.stacks.is_none());
+}
+
+#[test]
+fn partial_application_is_attributed_to_its_function() {
+    let machine = machine("n-ary_funs/grab3.txt", false);
+    let profile = machine.profile().unwrap();
+    let restart = machine
+        .prog()
+        .iter()
+        .position(|(_, i)| i.mnemonic() == "RESTART");
+    assert_eq!(profile.function(restart.unwrap()), "L1");
+    assert_eq!(profile.calls.keys().collect::<Vec<_>>(), vec!["L1"]);
+}
+
+#[test]
+fn folded_stacks_and_json() {
+    let machine = machine("rec_funs/facto.txt", true);
+    let profile = machine.profile().unwrap();
+
+    let folded = profile.report(machine.prog(), ProfileFormat::Folded);
+    let lines: Vec<&str> = folded.lines().collect();
+    assert_eq!(lines.len(), 7);
+    assert!(lines[0].starts_with("<main> "));
+    assert!(lines[6].starts_with("<main>;L1;L1;L1;L1;L1;L1 "));
+    let total: u64 = lines
+        .iter()
+        .map(|l| l.rsplit(' ').next().unwrap().parse::<u64>().unwrap())
+        .sum();
+    assert_eq!(total, profile.total);
+
+    let json = profile.report(machine.prog(), ProfileFormat::Json);
+    assert!(json.starts_with(&format!("{{\"total\":{},", profile.total)));
+    assert!(json.contains("\"L1\":{\"instructions\":87,\"calls\":6}"));
+}
@@ -0,0 +1,116 @@
+use mini_zam::{
+    load_str, read_bytecode, write_bytecode, ErrorKind, Heap, Inst, IntMode, Mlvalue, Outcome, ZAM,
+};
+
+mod common;
+
+fn run(source: &str) -> (Outcome, ZAM) {
+    let mut machine = common::machine(source);
+    (machine.run().unwrap(), machine)
+}
+
+#[test]
+fn escapes_round_trip_through_the_text_format() {
+    let s = "tab\t \"guillemets\" \\ ;point-virgule\n\u{1}é";
+    let inst = Inst::ConstString(s.to_string());
+    let text = format!("\t{}\t; commentaire \"\n\tSTOP\n", inst);
+    let code = load_str(&text, "prog").unwrap();
+    assert_eq!(code[0], (None, inst));
+    assert_eq!(code[1], (None, Inst::Stop));
+}
+
+#[test]
+fn unterminated_strings_are_parse_errors() {
+    assert!(load_str("\tCONSTSTRING \"abc\n\tSTOP\n", "prog").is_err());
+    assert!(load_str("\tCONSTSTRING \"\\q\"\n\tSTOP\n", "prog").is_err());
+}
+
+#[test]
+fn strings_round_trip_through_bytecode() {
+    let code = load_str("\tCONSTSTRING \"a;b\\n\"\n\tSTOP\n", "prog").unwrap();
+    let mut bytes = Vec::new();
+    write_bytecode(&code, &mut bytes, false).unwrap();
+    assert_eq!(read_bytecode(&mut bytes.as_slice()).unwrap(), code);
+}
+
+#[test]
+fn unreachable_strings_are_collected() {
+    let mut heap = Heap::new();
+    let kept = heap.alloc_string(b"garde".to_vec());
+    heap.alloc_string(b"perdue".to_vec());
+    let block = heap.alloc(vec![kept.clone()]);
+    heap.collect(&[block]);
+    assert_eq!(heap.stats().live, 2);
+    let h = kept.as_string().unwrap();
+    assert_eq!(heap.get_string(h).unwrap(), b"garde");
+}
+
+#[test]
+fn concat_allocates_a_fresh_string() {
+    let (outcome, machine) =
+        run("\tCONSTSTRING \"b\"\n\tPUSH\n\tCONSTSTRING \"a\"\n\tPRIM ^\n\tSTOP\n");
+    let s = match outcome {
+        Outcome::Value(Mlvalue::Chaine(h)) => h,
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(machine.heap().get_string(s).unwrap(), b"ab");
+    assert_eq!(machine.heap().size(), 3);
+}
+
+#[test]
+fn int_of_string_raises_failure() {
+    for bad in &["", "-", "0x", "_1", "1 ", "99999999999999999999"] {
+        let source = format!("\tCONSTSTRING \"{}\"\n\tPRIM int_of_string\n\tSTOP\n", bad);
+        let (outcome, machine) = run(&source);
+        assert_eq!(
+            common::predefined(&machine, &outcome).as_deref(),
+            Some("Failure"),
+            "{:?}",
+            bad
+        );
+    }
+}
+
+#[test]
+fn failure_is_not_the_third_declared_exception() {
+    //C est numérotée 2, l'ancienne valeur de Failure
+    let mut machine = common::compiled(
+        "exception A\nexception B\nexception C\n\
+         let f s = try int_of_string s with C -> 1 | Failure -> 2\n\
+         let g x = try raise C with Failure -> 3 | C -> x\n\
+         let _ = f \"x\" * 10 + g 4",
+    );
+    assert_eq!(machine.run().unwrap(), Outcome::Value(Mlvalue::Entier(24)));
+}
+
+#[test]
+fn int_of_string_follows_the_integer_width() {
+    let source = "\tCONSTSTRING \"0x7fff_ffff_ffff_ffff\"\n\tPRIM int_of_string\n\tSTOP\n";
+    let mut machine = common::machine(source);
+    machine.set_int_mode(IntMode::OCaml);
+    //au-delà de max_int en hexadécimal, la valeur revient aux négatifs
+    assert_eq!(machine.run().unwrap(), Outcome::Value(Mlvalue::Entier(-1)));
+
+    let max = "\tCONSTSTRING \"4611686018427387904\"\n\tPRIM int_of_string\n\tSTOP\n";
+    let mut machine = common::machine(max);
+    machine.set_int_mode(IntMode::OCaml);
+    let outcome = machine.run().unwrap();
+    assert_eq!(
+        common::predefined(&machine, &outcome).as_deref(),
+        Some("Failure")
+    );
+    assert_eq!(
+        run(max).0,
+        Outcome::Value(Mlvalue::Entier(4611686018427387904))
+    );
+}
+
+#[test]
+fn string_sub_rejects_overflowing_bounds() {
+    let source = format!(
+        "\tCONST {}\n\tPUSH\n\tCONST 1\n\tPUSH\n\tCONSTSTRING \"abc\"\n\tPRIM string_sub\n\tSTOP\n",
+        i64::MAX
+    );
+    let err = common::run(&source).unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::BadOperand(_)));
+}
@@ -0,0 +1,18 @@
+(* chaînes : littéraux, concaténation et conversions *)
+exception Bad
+
+let greet name = "bonjour, " ^ name ^ " !\n"
+
+let () = print_string (greet "monde")
+
+let n = int_of_string "4_2" + String.length "abc"
+
+let () = print_string (string_of_int n ^ "\n")
+
+let parse s = try int_of_string s with Failure -> -1
+
+let () = print_string (string_of_int (parse "12x") ^ "\n")
+
+let () = if String.length "" <> 0 then raise Bad
+
+let _ = int_of_string "nope"
@@ -0,0 +1,129 @@
+use mini_zam::linker::link;
+use mini_zam::{
+    disasm, load_str, optimize, read_bytecode, write_bytecode, DisasmOptions, ErrorKind, Inst,
+    LinkError, Mlvalue, Outcome, Passes,
+};
+
+mod common;
+
+const PROG: &str = "\tACC 0
+\tSWITCH [L1,L2] [L3]
+L1:\tCONST 10
+\tSTOP
+L2:\tCONST 20
+\tSTOP
+L3:\tCONST 30
+\tSTOP
+";
+
+fn labels(names: &[&str]) -> Box<[String]> {
+    names.iter().map(|l| l.to_string()).collect()
+}
+
+//exécute PROG avec la valeur construite par setup au sommet de la pile
+fn dispatch(setup: &str) -> Result<Outcome, ErrorKind> {
+    let source = format!("{}\tPUSH\n{}", setup, PROG);
+    common::run(&source).map_err(|e| e.kind)
+}
+
+#[test]
+fn label_lists_are_parsed_and_printed() {
+    let code = load_str(PROG, "prog").unwrap();
+    assert_eq!(
+        code[1].1,
+        Inst::Switch(labels(&["L1", "L2"]), labels(&["L3"]))
+    );
+    assert_eq!(disasm(&code, &DisasmOptions::default()), PROG);
+
+    let empty = load_str("L1:\tSWITCH [] [L1]\n", "prog").unwrap();
+    assert_eq!(empty[0].1, Inst::Switch(labels(&[]), labels(&["L1"])));
+    assert_eq!(empty[0].1.to_string(), "SWITCH [] [L1]");
+
+    for bad in &["\tSWITCH [L1]\n", "\tSWITCH L1 []\n", "\tSWITCH [L1,] []\n"] {
+        assert!(load_str(bad, "prog").is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn integers_and_tags_select_their_case() {
+    let value = |n| Ok(Outcome::Value(Mlvalue::Entier(n)));
+    assert_eq!(dispatch("\tCONST 0\n"), value(10));
+    assert_eq!(dispatch("\tCONST 1\n"), value(20));
+    assert_eq!(dispatch("\tCONST 5\n\tMAKEBLOCK 1\n"), value(30));
+}
+
+#[test]
+fn missing_cases_are_errors() {
+    assert!(matches!(
+        dispatch("\tCONST 2\n"),
+        Err(ErrorKind::BadOperand(_))
+    ));
+    assert!(matches!(
+        dispatch("\tCONST -1\n"),
+        Err(ErrorKind::BadOperand(_))
+    ));
+    assert!(matches!(
+        dispatch("\tCONST 5\n\tMAKEBLOCK 1,1\n"),
+        Err(ErrorKind::BadOperand(_))
+    ));
+}
+
+#[test]
+fn undefined_labels_are_named() {
+    //load_str refuse les labels inconnus
+    let code = vec![
+        (None, Inst::Const(1)),
+        (None, Inst::Switch(labels(&["L1", "L9"]), labels(&[]))),
+        (Some("L1".to_string()), Inst::Stop),
+    ];
+    assert_eq!(
+        link(&code),
+        Err(vec![LinkError::Undefined {
+            label: "L9".to_string(),
+            pc: 1
+        }])
+    );
+    assert_eq!(
+        common::quiet(&code).run().unwrap_err().kind,
+        ErrorKind::UnknownLabel("L9".to_string())
+    );
+}
+
+#[test]
+fn targets_round_trip_through_bytecode() {
+    let code = load_str(PROG, "prog").unwrap();
+    let mut bytes = Vec::new();
+    write_bytecode(&code, &mut bytes, true).unwrap();
+    assert_eq!(read_bytecode(&mut bytes.as_slice()).unwrap(), code);
+}
+
+#[test]
+fn disasm_notes_every_target() {
+    let code = load_str(PROG, "prog").unwrap();
+    let options = DisasmOptions {
+        indices: true,
+        ..DisasmOptions::default()
+    };
+    let text = disasm(&code, &options);
+    assert!(text.contains("; 1, vers 2, 4, 6\n"), "{}", text);
+}
+
+#[test]
+fn optimizer_threads_cases_and_drops_dead_code() {
+    //le BRANCH retiré laisse L1 sur le STOP, L2 en devient un alias
+    let code = load_str(
+        "\tSWITCH [L1] [L2]\n\tCONST 0\nL1:\tBRANCH L2\nL2:\tSTOP\n",
+        "prog",
+    )
+    .unwrap();
+    let mut passes = Passes::none();
+    passes.set("jumps", true);
+    passes.set("dead-code", true);
+    assert_eq!(
+        optimize(&code, &passes),
+        vec![
+            (None, Inst::Switch(labels(&["L1"]), labels(&["L1"]))),
+            (Some("L1".to_string()), Inst::Stop),
+        ]
+    );
+}
@@ -0,0 +1,80 @@
+use mini_zam::mlvalue::{DOUBLE_TAG, STRING_TAG};
+use mini_zam::{load_str, read_bytecode, write_bytecode, Heap, Inst, Mlvalue, Outcome};
+
+mod common;
+
+fn run(source: &str) -> Result<Outcome, String> {
+    common::run(source).map_err(|e| e.to_string())
+}
+
+#[test]
+fn tag_is_optional_in_the_text_format() {
+    let code = load_str("\tMAKEBLOCK 2\n\tMAKEBLOCK 1,3\n\tSTOP\n", "prog").unwrap();
+    assert_eq!(code[0].1, Inst::Makeblock(2, 0));
+    assert_eq!(code[1].1, Inst::Makeblock(1, 3));
+    assert_eq!(code[0].1.to_string(), "MAKEBLOCK 2");
+    assert_eq!(code[1].1.to_string(), "MAKEBLOCK 1,3");
+    assert!(load_str("\tMAKEBLOCK 1,x\n", "prog").is_err());
+}
+
+#[test]
+fn gettag_reads_constructor_and_ocaml_tags() {
+    let block = "\tCONST 1\n\tMAKEBLOCK 1,4\n\tGETTAG\n\tSTOP\n";
+    assert_eq!(run(block), Ok(Outcome::Value(Mlvalue::Entier(4))));
+    let string = "\tCONSTSTRING \"a\"\n\tGETTAG\n\tSTOP\n";
+    assert_eq!(
+        run(string),
+        Ok(Outcome::Value(Mlvalue::Entier(STRING_TAG as i64)))
+    );
+    let float = "\tCONSTFLOAT 1.0\n\tGETTAG\n\tSTOP\n";
+    assert_eq!(
+        run(float),
+        Ok(Outcome::Value(Mlvalue::Entier(DOUBLE_TAG as i64)))
+    );
+    assert!(run("\tCONST 1\n\tGETTAG\n\tSTOP\n").is_err());
+}
+
+#[test]
+fn reserved_tags_are_rejected() {
+    assert!(run("\tCONST 1\n\tMAKEBLOCK 1,246\n\tSTOP\n").is_err());
+    assert!(run("\tCONST 1\n\tMAKEBLOCK 1,-1\n\tSTOP\n").is_err());
+}
+
+#[test]
+fn helpers_inspect_tags() {
+    let mut heap = Heap::new();
+    let some = heap.alloc_tagged(1, vec![Mlvalue::Entier(3)]);
+    assert!(some.is_block());
+    assert!(some.has_tag(1, &heap));
+    assert!(!some.has_tag(0, &heap));
+    assert!(!Mlvalue::Entier(1).is_block());
+    assert!(!Mlvalue::Entier(1).has_tag(1, &heap));
+    assert_eq!(some.tag(&heap), Ok(1));
+    assert_eq!(heap.show(&some), "Block(1: [Entier(3)])");
+}
+
+#[test]
+fn tags_round_trip_through_bytecode() {
+    let code = load_str("\tCONST 0\n\tMAKEBLOCK 1,7\n\tGETTAG\n\tSTOP\n", "prog").unwrap();
+    let mut bytes = Vec::new();
+    write_bytecode(&code, &mut bytes, false).unwrap();
+    assert_eq!(read_bytecode(&mut bytes.as_slice()).unwrap(), code);
+}
+
+#[test]
+fn version_1_files_have_untagged_blocks() {
+    let mut bytes = b"MZAM".to_vec();
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.push(0);
+    let names = ["CONST", "MAKEBLOCK", "STOP"];
+    bytes.push(names.len() as u8);
+    for name in names.iter() {
+        bytes.push(name.len() as u8);
+        bytes.extend_from_slice(name.as_bytes());
+    }
+    //CONST 5, MAKEBLOCK 1, STOP
+    bytes.extend_from_slice(&[3, 0, 5, 1, 1, 2]);
+    let code = read_bytecode(&mut bytes.as_slice()).unwrap();
+    assert_eq!(code[1].1, Inst::Makeblock(1, 0));
+    assert_eq!(code[2].1, Inst::Stop);
+}